@@ -1,50 +1,65 @@
+// The services expose more than this binary wires up yet.
+#![allow(dead_code)]
+// The `select!` of crossbeam-channel 0.2 checks the old `cargo-clippy` feature.
+#![allow(unexpected_cfgs)]
+
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate crossbeam_channel as channel;
 extern crate parking_lot;
 extern crate fnv;
+extern crate lru_cache;
+extern crate rocksdb;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate bincode;
+extern crate blake2_rfc;
+#[cfg(test)]
+extern crate tempfile;
 
 mod util;
+mod verification;
 mod services;
 
 use util::{
     Shared,
-    CuckooEngine,
     Consensus,
+    RocksDBStore,
 };
 use services::chain::{
     ChainService,
     ChainController,
-    ChainReceivers
 };
 use services::miner::{
     MinerService,
     MinerController,
-    MinerReceivers
 };
+use services::notify::NotifyService;
 use services::tx_pool::{
     TransactionPoolService,
     TransactionPoolController,
-    TransactionPoolReceivers
+    TransactionPoolConfig,
 };
 use services::block_verifier::{
     BlockVerifierService,
     BlockVerifierController,
-    BlockVerifierReceivers
+    BlockVerifierConfig,
 };
 
 fn main() {
     let consensus = Consensus::default();
-    let pow = CuckooEngine::default();
-    let shared = Shared::new(consensus, pow);
+    let pow = consensus.pow.engine();
+    let store = RocksDBStore::open("data/db").expect("Open database failed");
+    let shared = Shared::new(consensus, store).expect("Initialize chain failed");
 
     let (_handle, notify_controller) = NotifyService::default().start(Some("notify"));
 
-    let (chain_controller, chain_receivers) = ChainController::new();
+    let (chain_controller, chain_receivers) = ChainController::new(&shared);
     let (miner_controller, miner_receivers) = MinerController::new();
     let (txpool_controller, txpool_receivers) = TransactionPoolController::new();
-    let (block_verifier_controller, block_verifier_receivers) = BlockVerifierController::new();
+    let (_block_verifier_controller, block_verifier_receivers) = BlockVerifierController::new();
 
     let chain_handle = ChainService::new(
         shared.clone(),
@@ -57,17 +72,19 @@ fn main() {
         pow.clone(),
         chain_controller.clone(),
         txpool_controller.clone(),
-        notify_controller.clone(),
+        &notify_controller,
     ).start(miner_receivers);
 
     let txpool_handle = TransactionPoolService::new(
         shared.clone(),
-        notify_controller.clone()
+        notify_controller.clone(),
+        TransactionPoolConfig::default(),
     ).start(txpool_receivers);
     
     let block_verifier_handle = BlockVerifierService::new(
         shared.clone(),
         pow.clone(),
+        BlockVerifierConfig::default(),
     ).start(block_verifier_receivers);
 
     for handle in [
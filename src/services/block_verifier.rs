@@ -1,15 +1,43 @@
-
 use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use channel::{self, Sender, Receiver};
+use parking_lot::Mutex;
 
 use util::{
     Request,
+    Shared,
+    ChainStore,
+    PowEngine,
     IndexedBlock
 };
+use verification::{
+    BlockVerifier,
+    BodyVerifier,
+    Verifier,
+    VerificationCache,
+    Error,
+};
+
+pub const DEFAULT_TRANSACTION_WORKERS: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct BlockVerifierConfig {
+    /// Number of threads verifying the transactions of a block, 0 to verify on the service
+    /// thread
+    pub transaction_workers: usize,
+}
+
+impl Default for BlockVerifierConfig {
+    fn default() -> Self {
+        BlockVerifierConfig {
+            transaction_workers: DEFAULT_TRANSACTION_WORKERS,
+        }
+    }
+}
 
-pub struct BlockVerifierService<CS, P> {
-    shared: Shared<CS>,
-    pow: P,
+pub struct BlockVerifierService {
+    verifier: BlockVerifier,
+    cache: Arc<Mutex<VerificationCache>>,
 }
 
 
@@ -40,11 +68,22 @@ impl BlockVerifierController {
     }
 }
 
-impl<CS, P> BlockVerifierService<CS, P>
-where
-      CS: ChainStore,
-      P: PowEngine,
-{
+impl BlockVerifierService {
+    pub fn new<CS, P>(
+        shared: Shared<CS>,
+        pow: P,
+        config: BlockVerifierConfig,
+    ) -> BlockVerifierService
+    where
+        CS: ChainStore + Send + Sync + 'static,
+        P: PowEngine + Clone + Send + 'static,
+    {
+        BlockVerifierService {
+            verifier: BlockVerifier::new(&shared, &pow, config.transaction_workers),
+            cache: Arc::clone(&shared.verification_cache),
+        }
+    }
+
     pub fn start(self, receivers: BlockVerifierReceivers) -> JoinHandle<()> {
         thread::spawn(move || loop {
             select! {
@@ -59,6 +98,66 @@ where
     }
 
     fn verify(&self, block: Arc<IndexedBlock>) -> Result<(), Error> {
+        // The hash covers the body only through the header commitments, a cached hash must
+        // not vouch for another body.
+        BodyVerifier.verify(&block)?;
+        let hash = block.hash();
+        if self.cache.lock().contains_block(&hash) {
+            return Ok(());
+        }
+        self.verifier.verify(&block)?;
+        self.cache.lock().insert_block(hash);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{transactions_root, Consensus, DummyPowEngine, Header, MemoryStore, Transaction};
+    use verification::BodyError;
+
+    #[test]
+    fn test_cached_block_body() {
+        let shared =
+            Shared::new(Consensus::default(), MemoryStore::default()).expect("init should be ok");
+        let config = BlockVerifierConfig {
+            transaction_workers: 0,
+        };
+        let service = BlockVerifierService::new(shared.clone(), DummyPowEngine::default(), config);
+        let genesis = shared.consensus.genesis_block.header().clone();
+        let transactions = vec![Transaction::new_cellbase(0, vec![]).into()];
+        let header = Header {
+            parent_hash: genesis.hash(),
+            number: 1,
+            timestamp: genesis.timestamp + 1,
+            difficulty: shared
+                .calculate_difficulty(&genesis)
+                .expect("calculate difficulty should be ok"),
+            transactions_root: transactions_root(&transactions),
+            ..Header::default()
+        };
+        let block = Arc::new(IndexedBlock::new(header.clone(), vec![], transactions, vec![]));
+        assert_eq!(service.verify(Arc::clone(&block)), Ok(()));
+
+        // Another body under the cached header is not a cache hit
+        let tampered_transactions = vec![Transaction::new_cellbase(1, vec![]).into()];
+        let tampered = Arc::new(IndexedBlock::new(
+            header.clone(),
+            vec![],
+            tampered_transactions.clone(),
+            vec![],
+        ));
+        assert_eq!(tampered.hash(), block.hash());
+        assert_eq!(
+            service.verify(tampered),
+            Err(Error::Body(BodyError::TransactionsRoot {
+                expected: header.transactions_root,
+                actual: transactions_root(&tampered_transactions),
+            }))
+        );
+
+        assert_eq!(service.verify(block), Ok(()));
+        assert_eq!(service.cache.lock().stats().block_hits, 1);
+    }
+}
@@ -1,18 +1,37 @@
 
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use channel::{self, Sender, Receiver};
+use parking_lot::{Mutex, RwLock};
 
 use util::{
     Request,
-    BlockNumber,
+    Shared,
+    ChainStore,
+    StoreBatch,
+    BlockExt,
+    StoreError,
+    IndexedBlock,
+    TipHeader,
+    H256,
 };
+use services::miner::MinerController;
+use services::notify::{ForkBlocks, NotifyController};
+use services::orphan_block_pool::OrphanBlockPool;
 
-pub struct TipHeader {
-    number: BlockNumber,
+#[derive(Debug)]
+pub enum Error {
+    /// The parent block has not been stored, the block is kept in the orphan pool
+    UnknownParent,
+    /// The main chain index is inconsistent with the stored blocks
+    MissingBlock,
+    /// Failed to read or persist the chain
+    Store(StoreError),
 }
 
-impl TipHeader {
-    pub fn number(&self) -> BlockNumber {
-        0
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Error::Store(err)
     }
 }
 
@@ -20,26 +39,36 @@ pub struct ChainService<CS> {
     shared: Shared<CS>,
     miner: MinerController,
     notify: NotifyController,
+    orphan_blocks: Mutex<OrphanBlockPool>,
 }
 
+#[derive(Clone)]
 pub struct ChainController {
-    process_block_sender: Sender<Request<IndexedBlock, Result<(), Error>>>,
+    tip: Arc<RwLock<TipHeader>>,
+    process_block_sender: Sender<Request<Arc<IndexedBlock>, Result<(), Error>>>,
+    missing_parents_sender: Sender<Request<(), Vec<H256>>>,
 }
 
 pub struct ChainReceivers {
-    process_block_receiver: Receiver<Request<IndexedBlock, Result<(), Error>>>,
+    process_block_receiver: Receiver<Request<Arc<IndexedBlock>, Result<(), Error>>>,
+    missing_parents_receiver: Receiver<Request<(), Vec<H256>>>,
 }
 
-impl ChainService {
+impl<CS: ChainStore + 'static> ChainService<CS> {
     pub fn new(
         shared: Shared<CS>,
         miner: MinerController,
         notify: NotifyController
-    ) -> ChainService {
-        ChainService { shared, miner, notify }
+    ) -> ChainService<CS> {
+        ChainService {
+            shared,
+            miner,
+            notify,
+            orphan_blocks: Mutex::new(OrphanBlockPool::default()),
+        }
     }
 
-    pub fn start(mut self, receivers: ChainReceivers) -> JoinHandle<()> {
+    pub fn start(self, receivers: ChainReceivers) -> JoinHandle<()> {
         thread::spawn(move || loop {
             select! {
                 recv(receivers.process_block_receiver, msg) => match msg {
@@ -48,33 +77,172 @@ impl ChainService {
                     },
                     None => error!("channel closed"),
                 }
+                recv(receivers.missing_parents_receiver, msg) => match msg {
+                    Some(Request { responsor, .. }) => {
+                        responsor.send(self.orphan_blocks.lock().missing_parents());
+                    },
+                    None => error!("channel closed"),
+                }
             }
         })
     }
 
+    /// Connects the block, or keeps it in the orphan pool when its parent is unknown. Once a
+    /// block is connected, the orphans descending from it are connected as well.
     pub fn process_block(&self, block: Arc<IndexedBlock>) -> Result<(), Error> {
-        let new_best_block = true;
-        if new_best_block {
-            self.notify.notify_new_tip(Arc::clone(&block));
+        if let Err(err) = self.insert_block(Arc::clone(&block)) {
+            if let Error::UnknownParent = err {
+                self.orphan_blocks.lock().insert(block);
+            }
+            return Err(err);
+        }
+
+        let descendants = self.orphan_blocks.lock().remove_descendants(&block.hash());
+        for descendant in descendants {
+            // A descendant whose ancestor was rejected becomes an orphan again.
+            if let Err(err) = self.process_block(Arc::clone(&descendant)) {
+                warn!(
+                    target: "chain",
+                    "orphan block {} rejected: {:?}",
+                    descendant.number(),
+                    err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the block and switches the main chain to it when its total difficulty is higher.
+    ///
+    /// Every stored block, in the main chain or in a side chain, has its total difficulty
+    /// recorded in a `BlockExt`, so a side chain overtaking the tip is detected as soon as the
+    /// block which makes it heavier arrives. Ties keep the current tip.
+    ///
+    /// A block extending the tip is published with `notify_new_tip`, a reorg only with
+    /// `notify_switch_fork`, and a side chain block is sent to the miner as an uncle.
+    fn insert_block(&self, block: Arc<IndexedBlock>) -> Result<(), Error> {
+        let store = &self.shared.store;
+        let hash = block.hash();
+        if store.get_block_ext(&hash)?.is_some() {
+            return Ok(());
+        }
+
+        let parent_ext = store
+            .get_block_ext(&block.header().parent_hash)?
+            .ok_or(Error::UnknownParent)?;
+        let ext = BlockExt {
+            total_difficulty: parent_ext
+                .total_difficulty
+                .saturating_add(block.header().difficulty),
+        };
+
+        let tip_hash = store.get_tip()?;
+        let tip_ext = match tip_hash {
+            Some(tip_hash) => store.get_block_ext(&tip_hash)?,
+            None => None,
+        };
+        let new_best_block =
+            tip_ext.is_none_or(|tip_ext| ext.total_difficulty > tip_ext.total_difficulty);
+
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&block);
+        batch.insert_block_ext(hash, ext);
+
+        if !new_best_block {
+            store.write(batch)?;
+            self.miner.add_uncle((*block).clone());
+            return Ok(());
+        }
+
+        let fork = if tip_hash == Some(block.header().parent_hash) {
+            None
+        } else {
+            Some(self.find_fork(&block)?)
+        };
+        if let Some(ref fork) = fork {
+            for detached in &fork.detached_blocks {
+                batch.detach_block(detached);
+            }
+            for attached in &fork.attached_blocks {
+                batch.attach_block(attached);
+            }
         } else {
-            self.miner.ad_uncle(Arc::clone(&block));
+            batch.attach_block(&block);
+        }
+        batch.insert_tip(hash);
+        store.write(batch)?;
+        *self.shared.tip.write() = TipHeader::new(block.header(), ext.total_difficulty);
+
+        match fork {
+            Some(fork) => {
+                info!(
+                    target: "chain",
+                    "switch fork at {}: detached {} blocks, attached {} blocks",
+                    block.number(),
+                    fork.detached_blocks.len(),
+                    fork.attached_blocks.len()
+                );
+                self.notify.notify_switch_fork(Arc::new(fork));
+            }
+            None => self.notify.notify_new_tip(Arc::clone(&block)),
         }
         Ok(())
     }
+
+    /// Walks back from `block` to the main chain, collecting the blocks to detach from the
+    /// current main chain and the blocks to attach, `block` included. See `ForkBlocks` for the
+    /// order.
+    fn find_fork(&self, block: &IndexedBlock) -> Result<ForkBlocks, Error> {
+        let store = &self.shared.store;
+
+        let mut attached_blocks = vec![block.clone()];
+        let mut ancestor = store
+            .get_header(&block.header().parent_hash)?
+            .ok_or(Error::UnknownParent)?;
+        while store.get_block_hash(ancestor.number)? != Some(ancestor.hash()) {
+            let hash = ancestor.hash();
+            attached_blocks.push(store.get_block(&hash)?.ok_or(Error::MissingBlock)?);
+            ancestor = store
+                .get_header(&ancestor.parent_hash)?
+                .ok_or(Error::MissingBlock)?;
+        }
+        attached_blocks.reverse();
+
+        let mut detached_blocks = Vec::new();
+        let mut number = ancestor.number + 1;
+        while let Some(hash) = store.get_block_hash(number)? {
+            detached_blocks.push(store.get_block(&hash)?.ok_or(Error::MissingBlock)?);
+            number += 1;
+        }
+
+        Ok(ForkBlocks {
+            ancestor,
+            detached_blocks,
+            attached_blocks,
+        })
+    }
 }
 
 impl ChainController {
 
-    pub fn new() -> (ChainController, ChainReceivers) {
+    pub fn new<CS>(shared: &Shared<CS>) -> (ChainController, ChainReceivers) {
         let (process_block_sender, process_block_receiver) = channel::bounded(32);
+        let (missing_parents_sender, missing_parents_receiver) = channel::bounded(32);
         (
-            ChainController { process_block_sender },
-            ChainReceivers { process_block_receiver }
+            ChainController {
+                tip: Arc::clone(&shared.tip),
+                process_block_sender,
+                missing_parents_sender,
+            },
+            ChainReceivers {
+                process_block_receiver,
+                missing_parents_receiver,
+            }
         )
     }
 
     pub fn tip_header(&self) -> TipHeader {
-        TipHeader { number: 0 }
+        *self.tip.read()
     }
 
     pub fn process_block(&self, block: Arc<IndexedBlock>) -> Result<(), Error> {
@@ -85,4 +253,155 @@ impl ChainController {
         });
         response.recv().expect("Process block failed")
     }
+
+    /// The parents of the orphan blocks, which the synchronizer should request.
+    pub fn missing_parents(&self) -> Vec<H256> {
+        let (responsor, response) = channel::bounded(1);
+        self.missing_parents_sender.send(Request {
+            responsor,
+            arguments: ()
+        });
+        response.recv().expect("Missing parents failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use services::miner::MinerReceivers;
+    use services::notify::{MsgNewTip, MsgSwitchFork, NotifyService, Subscription};
+    use util::{Consensus, Header, MemoryStore};
+
+    fn new_chain() -> (
+        ChainService<MemoryStore>,
+        MinerReceivers,
+        Subscription<MsgNewTip>,
+        Subscription<MsgSwitchFork>,
+    ) {
+        let shared =
+            Shared::new(Consensus::default(), MemoryStore::default()).expect("init should be ok");
+        let (miner, miner_receivers) = MinerController::new();
+        let (_, notify) = NotifyService::default().start::<&str>(None);
+        let new_tip_receiver = notify.subscribe_new_tip("test");
+        let switch_fork_receiver = notify.subscribe_switch_fork("test");
+        (
+            ChainService::new(shared, miner, notify),
+            miner_receivers,
+            new_tip_receiver,
+            switch_fork_receiver,
+        )
+    }
+
+    /// A child of `parent`, the nonce tells apart siblings.
+    fn new_block(parent: &Header, difficulty: u64, nonce: u64) -> Arc<IndexedBlock> {
+        let header = Header {
+            parent_hash: parent.hash(),
+            number: parent.number + 1,
+            difficulty,
+            nonce,
+            ..Header::default()
+        };
+        Arc::new(IndexedBlock::new(header, vec![], vec![], vec![]))
+    }
+
+    fn genesis(chain: &ChainService<MemoryStore>) -> Header {
+        chain.shared.consensus.genesis_block.header().clone()
+    }
+
+    #[test]
+    fn test_side_chain() {
+        let (chain, miner_receivers, new_tip_receiver, _) = new_chain();
+        let genesis = genesis(&chain);
+        let a1 = new_block(&genesis, 10, 0);
+        let b1 = new_block(&genesis, 10, 1);
+
+        chain.process_block(Arc::clone(&a1)).expect("process block should be ok");
+        assert_eq!(new_tip_receiver.recv(), Some(Arc::clone(&a1)));
+
+        // Ties keep the current tip
+        chain.process_block(Arc::clone(&b1)).expect("process block should be ok");
+        assert_eq!(chain.shared.tip_header().hash(), a1.hash());
+        assert_eq!(chain.shared.store.get_block_hash(1), Ok(Some(a1.hash())));
+        assert_eq!(chain.shared.store.get_block(&b1.hash()), Ok(Some((*b1).clone())));
+        assert_eq!(miner_receivers.uncle_receiver.recv(), Some((*b1).clone()));
+    }
+
+    #[test]
+    fn test_switch_fork() {
+        let (chain, _miner_receivers, new_tip_receiver, switch_fork_receiver) = new_chain();
+        let genesis = genesis(&chain);
+        let a1 = new_block(&genesis, 10, 0);
+        let a2 = new_block(a1.header(), 10, 0);
+        let b1 = new_block(&genesis, 10, 1);
+        let b2 = new_block(b1.header(), 15, 1);
+        for block in &[&a1, &a2, &b1] {
+            chain.process_block(Arc::clone(block)).expect("process block should be ok");
+        }
+        assert_eq!(chain.shared.tip_header().hash(), a2.hash());
+
+        chain.process_block(Arc::clone(&b2)).expect("process block should be ok");
+        assert_eq!(chain.shared.tip_header().hash(), b2.hash());
+        assert_eq!(chain.shared.tip_header().total_difficulty(), 25);
+        assert_eq!(chain.shared.store.get_block_hash(1), Ok(Some(b1.hash())));
+        assert_eq!(chain.shared.store.get_block_hash(2), Ok(Some(b2.hash())));
+        assert_eq!(chain.shared.store.get_tip(), Ok(Some(b2.hash())));
+
+        let fork = switch_fork_receiver.recv().expect("switch fork should be notified");
+        assert_eq!(fork.ancestor, genesis);
+        assert_eq!(fork.detached_blocks, vec![(*a1).clone(), (*a2).clone()]);
+        assert_eq!(fork.attached_blocks, vec![(*b1).clone(), (*b2).clone()]);
+        // The reorg is not published as a new tip
+        assert_eq!(new_tip_receiver.recv(), Some(Arc::clone(&a1)));
+        assert_eq!(new_tip_receiver.recv(), Some(Arc::clone(&a2)));
+        assert_eq!(new_tip_receiver.try_recv(), None);
+    }
+
+    #[test]
+    fn test_longer_lighter_chain() {
+        let (chain, miner_receivers, _, _) = new_chain();
+        let genesis = genesis(&chain);
+        let a1 = new_block(&genesis, 100, 0);
+        let b1 = new_block(&genesis, 10, 1);
+        let b2 = new_block(b1.header(), 10, 1);
+        let b3 = new_block(b2.header(), 10, 1);
+        for block in &[&a1, &b1, &b2, &b3] {
+            chain.process_block(Arc::clone(block)).expect("process block should be ok");
+        }
+
+        assert_eq!(chain.shared.tip_header().hash(), a1.hash());
+        assert_eq!(chain.shared.store.get_block_hash(1), Ok(Some(a1.hash())));
+        assert_eq!(chain.shared.store.get_block_hash(2), Ok(None));
+        assert_eq!(
+            chain.shared.store.get_block_ext(&b3.hash()),
+            Ok(Some(BlockExt {
+                total_difficulty: 30
+            }))
+        );
+        for block in &[b1, b2, b3] {
+            assert_eq!(miner_receivers.uncle_receiver.recv(), Some((**block).clone()));
+        }
+    }
+
+    #[test]
+    fn test_orphan_blocks() {
+        let (chain, _miner_receivers, new_tip_receiver, _) = new_chain();
+        let genesis = genesis(&chain);
+        let a1 = new_block(&genesis, 10, 0);
+        let a2 = new_block(a1.header(), 10, 0);
+
+        match chain.process_block(Arc::clone(&a2)) {
+            Err(Error::UnknownParent) => {}
+            result => panic!("unknown parent expected, got {:?}", result),
+        }
+        assert_eq!(chain.orphan_blocks.lock().missing_parents(), vec![a1.hash()]);
+        assert_eq!(chain.shared.store.get_block(&a2.hash()), Ok(None));
+
+        // The orphan is connected once its parent arrives
+        chain.process_block(Arc::clone(&a1)).expect("process block should be ok");
+        assert_eq!(chain.shared.tip_header().hash(), a2.hash());
+        assert_eq!(chain.shared.store.get_block_hash(2), Ok(Some(a2.hash())));
+        assert!(chain.orphan_blocks.lock().missing_parents().is_empty());
+        assert_eq!(new_tip_receiver.recv(), Some(a1));
+        assert_eq!(new_tip_receiver.recv(), Some(a2));
+    }
 }
@@ -1,32 +1,55 @@
 
+use std::cmp;
 use std::thread;
 use std::thread::JoinHandle;
 use std::sync::Arc;
 use channel::{self, Sender, Receiver};
 use fnv::FnvHashMap;
 
-use service::{Request, Service};
-use services::notify::{NotifyController, MINER_SUBSCRIBER};
+use services::notify::{
+    NotifyController,
+    Subscription,
+    MsgNewTransaction,
+    MsgNewTip,
+    MsgSwitchFork,
+    MINER_SUBSCRIBER,
+};
 use services::chain::ChainController;
 use services::tx_pool::TransactionPoolController;
 use util::{
     H256,
+    Header,
     IndexedBlock,
+    IndexedTransaction,
+    Transaction,
     Shared,
     BlockNumber,
     PowEngine,
     ChainStore,
+    StoreError,
+    now_ms,
+    transactions_root,
+    uncles_hash,
 };
 
+/// Nonces tried on a block template before building a new one from the latest tip,
+/// transactions and time.
+pub const NONCE_ATTEMPTS: u64 = 1000;
+pub const MAX_PROPOSAL_TRANSACTIONS: usize = 1000;
+pub const MAX_COMMIT_TRANSACTIONS: usize = 1000;
+
 pub struct MinerService<S, P> {
     shared: Shared<S>,
     pow: P,
     chain: ChainController,
     tx_pool: TransactionPoolController,
-    new_transaction_receiver: Receiver<()>,
-    new_tip_receiver: Receiver<Arc<IndexedBlock>>,
+    new_transaction_receiver: Subscription<MsgNewTransaction>,
+    new_tip_receiver: Subscription<MsgNewTip>,
+    switch_fork_receiver: Subscription<MsgSwitchFork>,
     candidate_uncles: FnvHashMap<H256, IndexedBlock>,
     mining_number: BlockNumber,
+    /// The next nonce to try
+    nonce: u64,
 }
 
 impl<S, P> MinerService<S, P>
@@ -45,6 +68,7 @@ where
         let mining_number = chain.tip_header().number();
         let new_transaction_receiver = notify.subscribe_new_transaction(MINER_SUBSCRIBER);
         let new_tip_receiver = notify.subscribe_new_tip(MINER_SUBSCRIBER);
+        let switch_fork_receiver = notify.subscribe_switch_fork(MINER_SUBSCRIBER);
 
         MinerService {
             shared,
@@ -53,30 +77,144 @@ where
             tx_pool,
             new_transaction_receiver,
             new_tip_receiver,
+            switch_fork_receiver,
             mining_number,
             candidate_uncles: FnvHashMap::default(),
+            nonce: 0,
         }
     }
 
+    /// Builds a block template on the tip and tries `NONCE_ATTEMPTS` nonces on it, a solved
+    /// block is sent to the chain.
     fn commit_new_block(&mut self) {
-        unimplemented!();
+        let template = match self.block_template() {
+            Some(template) => template,
+            None => return,
+        };
+        let mut header = template.header().clone();
+        let start = self.nonce;
+        self.nonce = self.nonce.wrapping_add(NONCE_ATTEMPTS);
+        let solution = (0..NONCE_ATTEMPTS)
+            .map(|attempt| start.wrapping_add(attempt))
+            .filter_map(|nonce| self.pow.solve(&header, nonce).map(|proof| (nonce, proof)))
+            .next();
+        let (nonce, proof) = match solution {
+            Some(solution) => solution,
+            None => return,
+        };
+        header.nonce = nonce;
+        header.proof = proof;
+
+        let block = IndexedBlock::new(
+            header,
+            template.uncles().to_vec(),
+            template.commit_transactions().to_vec(),
+            template.proposal_transactions().to_vec(),
+        );
+        let number = block.number();
+        match self.chain.process_block(Arc::new(block.clone())) {
+            Ok(()) => {
+                info!(target: "miner", "mined block {} {}", number, block.hash());
+                for uncle in block.uncles() {
+                    self.candidate_uncles.remove(&uncle.hash());
+                }
+            }
+            Err(err) => error!(target: "miner", "mined block {} rejected: {:?}", number, err),
+        }
+    }
+
+    /// The block on top of the tip with the cellbase, the candidate uncles and the transactions
+    /// selected by the pool, not solved yet.
+    fn block_template(&mut self) -> Option<IndexedBlock> {
+        let tip = self.chain.tip_header();
+        let parent = match self.shared.store.get_header(&tip.hash()) {
+            Ok(Some(parent)) => parent,
+            Ok(None) => {
+                error!(target: "miner", "tip header {} not found", tip.hash());
+                return None;
+            }
+            Err(err) => {
+                error!(target: "miner", "read tip header {} failed: {}", tip.hash(), err);
+                return None;
+            }
+        };
+        let number = parent.number + 1;
+        let uncles = self.select_uncles(number);
+        let (proposals, transactions) = self
+            .tx_pool
+            .get_proposal_commit_txs(MAX_PROPOSAL_TRANSACTIONS, MAX_COMMIT_TRANSACTIONS);
+        let mut commits = vec![self.cellbase(number)];
+        commits.extend(transactions);
+        match self.new_header(&parent, &uncles, &commits, &proposals) {
+            Ok(header) => Some(IndexedBlock::new(header, uncles, commits, proposals)),
+            Err(err) => {
+                error!(target: "miner", "calculate difficulty failed: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Drops the candidate uncles too old for the block `number`, and returns the most recent
+    /// ones which can be included.
+    fn select_uncles(&mut self, number: BlockNumber) -> Vec<Header> {
+        let max_age = self.shared.consensus.max_uncles_age as u64;
+        self.candidate_uncles.retain(|_, uncle| uncle.number() + max_age >= number);
+        let mut uncles = self
+            .candidate_uncles
+            .values()
+            .filter(|uncle| uncle.number() < number)
+            .map(|uncle| uncle.header().clone())
+            .collect::<Vec<_>>();
+        uncles.sort_by(|a, b| b.number.cmp(&a.number).then(a.hash().cmp(&b.hash())));
+        uncles.truncate(self.shared.consensus.max_uncles_len);
+        uncles
+    }
+
+    /// The reward of the block `number`, whose number makes every cellbase unique.
+    fn cellbase(&self, number: BlockNumber) -> IndexedTransaction {
+        let reward = self.shared.consensus.initial_block_reward;
+        Transaction::new_cellbase(reward, number.to_le_bytes().to_vec()).into()
+    }
+
+    /// Creates the header template on top of `parent` committing to the body, nonce and proof
+    /// are found by mining.
+    fn new_header(
+        &self,
+        parent: &Header,
+        uncles: &[Header],
+        commits: &[IndexedTransaction],
+        proposals: &[IndexedTransaction],
+    ) -> Result<Header, StoreError> {
+        Ok(Header {
+            parent_hash: parent.hash(),
+            number: parent.number + 1,
+            timestamp: cmp::max(now_ms(), parent.timestamp + 1),
+            difficulty: self.shared.calculate_difficulty(parent)?,
+            transactions_root: transactions_root(commits),
+            proposals_root: transactions_root(proposals),
+            uncles_hash: uncles_hash(uncles),
+            uncles_count: uncles.len() as u32,
+            nonce: 0,
+            proof: Vec::new(),
+        })
     }
 }
 
+#[derive(Clone)]
 pub struct MinerController {
     uncle_sender: Sender<IndexedBlock>,
 }
 
 pub struct MinerReceivers {
-    uncle_receiver: Receiver<IndexedBlock>,
+    pub(crate) uncle_receiver: Receiver<IndexedBlock>,
 }
 
 impl<S, P> MinerService<S, P>
 where
-    S: ChainStore,
-    P: PowEngine,
+    S: ChainStore + Send + Sync + 'static,
+    P: PowEngine + Send + 'static,
 {
-    fn start(mut self, receivers: MinerReceivers) -> JoinHandle<()> {
+    pub fn start(mut self, receivers: MinerReceivers) -> JoinHandle<()> {
         thread::spawn(move || {
             self.pow.init(self.mining_number);
 
@@ -88,6 +226,20 @@ where
                         }
                         None => error!(target: "miner", "uncle_receiver closed")
                     }
+                    // Templates are built from the latest tip and pool, the events only need
+                    // to be drained. A reorg changes the tip without a new tip event.
+                    recv(self.new_tip_receiver, msg) => match msg {
+                        Some(_) => {}
+                        None => error!(target: "miner", "new_tip_receiver closed")
+                    }
+                    recv(self.switch_fork_receiver, msg) => match msg {
+                        Some(_) => {}
+                        None => error!(target: "miner", "switch_fork_receiver closed")
+                    }
+                    recv(self.new_transaction_receiver, msg) => match msg {
+                        Some(_) => {}
+                        None => error!(target: "miner", "new_transaction_receiver closed")
+                    }
                     default => {
                         self.commit_new_block();
                     }
@@ -3,6 +3,9 @@ pub mod tx_pool;
 pub mod miner;
 pub mod notify;
 pub mod block_verifier;
+pub mod orphan_pool;
+pub mod orphan_block_pool;
+pub mod orphan_transaction_pool;
 pub mod relayer;
 pub mod synchronizer;
 pub mod network;
@@ -1,27 +1,314 @@
 
 use channel;
 use channel::{Receiver, Sender};
-use fnv::FnvHashMap;
-use std::sync::Arc;
+use fnv::{FnvHashMap, FnvHashSet};
+use std::any::{type_name, Any, TypeId};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::option;
+use std::sync::{Arc, Weak};
 use std::thread;
 use std::thread::JoinHandle;
 
 use util::{
     Request,
+    Header,
     IndexedBlock,
+    IndexedTransaction,
+    H256,
 };
 
 pub const MINER_SUBSCRIBER: &str = "miner";
 pub const TXS_POOL_SUBSCRIBER: &str = "txs_pool";
 
+/// The main chain switch from one branch to another.
+///
+/// Both lists are in ascending block number starting at the child of `ancestor`, so the last
+/// detached block is the old tip and the last attached block is the new tip.
 #[derive(Clone, PartialEq, Debug, Default)]
-pub struct ForkBlocks{}
+pub struct ForkBlocks {
+    /// The last block shared by both branches
+    pub ancestor: Header,
+    pub detached_blocks: Vec<IndexedBlock>,
+    pub attached_blocks: Vec<IndexedBlock>,
+}
+
+impl ForkBlocks {
+    /// Transactions committed only in the detached blocks, which must return to the pool.
+    ///
+    /// Cellbases are skipped, and the order follows the chain so parents come before children.
+    pub fn detached_transactions(&self) -> Vec<&IndexedTransaction> {
+        let attached = self
+            .attached_transactions()
+            .iter()
+            .map(|tx| tx.hash())
+            .collect::<FnvHashSet<_>>();
+        self.detached_blocks
+            .iter()
+            .flat_map(|block| block.commit_transactions().iter())
+            .filter(|tx| !tx.is_cellbase() && !attached.contains(&tx.hash()))
+            .collect()
+    }
+
+    /// Transactions committed in the attached blocks, which must be removed from the pool.
+    pub fn attached_transactions(&self) -> Vec<&IndexedTransaction> {
+        self.attached_blocks
+            .iter()
+            .flat_map(|block| block.commit_transactions().iter())
+            .filter(|tx| !tx.is_cellbase())
+            .collect()
+    }
+}
+
+/// Transactions evicted from the pool to keep it within its capacity, lowest fee rate first.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvictedTransactions(pub Vec<H256>);
 
 type StopSignal = ();
 pub type MsgNewTransaction = ();
+/// A block extending the main chain tip.
 pub type MsgNewTip = Arc<IndexedBlock>;
+/// A reorg, published instead of `MsgNewTip`: the last attached block is the new tip, so
+/// subscribers following the tip must subscribe to both.
 pub type MsgSwitchFork = Arc<ForkBlocks>;
-pub type NotifyRegister<M> = Sender<Request<(String, usize), Receiver<M>>>;
+pub type MsgEvictedTransactions = Arc<EvictedTransactions>;
+
+pub const DEFAULT_CHANNEL_SIZE: usize = 128;
+
+/// Any type which can be sent through `NotifyService`. Every distinct message type is a
+/// separate topic, so give each event its own type instead of reusing a generic payload.
+pub trait Message: Clone + Send + 'static {}
+
+impl<M: Clone + Send + 'static> Message for M {}
+
+/// What to do with a message when the subscriber's channel is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Block the notify thread until the subscriber receives a message.
+    Block,
+    /// Discard the new message.
+    DropNewest,
+    /// Discard the oldest message in the channel to make room for the new one.
+    DropOldest,
+    /// Unsubscribe the subscriber, it receives `None` after the pending messages.
+    Disconnect,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SubscribeOptions {
+    /// Channel capacity, must be positive unless the policy is `OverflowPolicy::Block`.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        SubscribeOptions {
+            capacity: DEFAULT_CHANNEL_SIZE,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// A request executed by the notify thread.
+trait Command: Send {
+    fn execute(self: Box<Self>, topics: &mut Topics);
+}
+
+type Subscribe<M> = Request<(String, SubscribeOptions), Subscription<M>>;
+
+struct Unsubscribe<M> {
+    request: Request<String, bool>,
+    topic: PhantomData<M>,
+}
+
+struct DroppedMessages<M> {
+    request: Request<String, Option<u64>>,
+    topic: PhantomData<M>,
+}
+
+struct Publish<M>(M);
+
+impl<M: Message> Command for Subscribe<M> {
+    fn execute(self: Box<Self>, topics: &mut Topics) {
+        let Request {
+            responsor,
+            arguments: (name, options),
+        } = *self;
+        debug!(target: "notify", "Register {} {:?} {:?}", type_name::<M>(), name, options);
+        let (sender, receiver) = channel::bounded::<M>(options.capacity);
+        let alive = Arc::new(());
+        let subscriber = Subscriber {
+            sender,
+            receiver: receiver.clone(),
+            alive: Arc::downgrade(&alive),
+            overflow: options.overflow,
+            dropped: 0,
+        };
+        topics.topic_mut::<M>().subscribers.insert(name, subscriber);
+        responsor.send(Subscription {
+            receiver,
+            _alive: alive,
+        });
+    }
+}
+
+impl<M: Message> Command for Unsubscribe<M> {
+    fn execute(self: Box<Self>, topics: &mut Topics) {
+        let Request {
+            responsor,
+            arguments: name,
+        } = self.request;
+        debug!(target: "notify", "Unregister {} {:?}", type_name::<M>(), name);
+        let removed = topics.topic_mut::<M>().subscribers.remove(&name).is_some();
+        responsor.send(removed);
+    }
+}
+
+impl<M: Message> Command for DroppedMessages<M> {
+    fn execute(self: Box<Self>, topics: &mut Topics) {
+        let Request {
+            responsor,
+            arguments: name,
+        } = self.request;
+        let dropped = topics
+            .topic_mut_if_exists::<M>()
+            .and_then(|topic| topic.subscribers.get(&name))
+            .map(|subscriber| subscriber.dropped);
+        responsor.send(dropped);
+    }
+}
+
+impl<M: Message> Command for Publish<M> {
+    fn execute(self: Box<Self>, topics: &mut Topics) {
+        trace!(target: "notify", "event {}", type_name::<M>());
+        if let Some(topic) = topics.topic_mut_if_exists::<M>() {
+            topic.prune();
+            let msg = self.0;
+            topic.subscribers.retain(|name, subscriber| {
+                let keep = subscriber.deliver(msg.clone());
+                if !keep {
+                    warn!(
+                        target: "notify",
+                        "Disconnect slow {} subscriber {:?}, dropped {} messages",
+                        type_name::<M>(),
+                        name,
+                        subscriber.dropped
+                    );
+                }
+                keep
+            });
+        }
+    }
+}
+
+/// The receiving end of a subscription.
+///
+/// The subscriber is pruned from its topic on the next notification after the subscription is
+/// dropped, use `NotifyController::unsubscribe` to remove it immediately.
+pub struct Subscription<M> {
+    receiver: Receiver<M>,
+    _alive: Arc<()>,
+}
+
+impl<M> Deref for Subscription<M> {
+    type Target = Receiver<M>;
+
+    fn deref(&self) -> &Receiver<M> {
+        &self.receiver
+    }
+}
+
+// Allows a subscription to be used directly in `select!`.
+impl<'a, M> IntoIterator for &'a Subscription<M> {
+    type Item = &'a Receiver<M>;
+    type IntoIter = option::IntoIter<&'a Receiver<M>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Some(&self.receiver).into_iter()
+    }
+}
+
+struct Subscriber<M> {
+    sender: Sender<M>,
+    // Used to discard the oldest message on overflow.
+    receiver: Receiver<M>,
+    alive: Weak<()>,
+    overflow: OverflowPolicy,
+    dropped: u64,
+}
+
+impl<M> Subscriber<M> {
+    /// Sends the message according to the overflow policy, returns false if the subscriber
+    /// should be disconnected.
+    ///
+    /// The notify thread is the only sender, so the channel cannot become full between the
+    /// check and the send.
+    fn deliver(&mut self, msg: M) -> bool {
+        if !self.sender.is_full() || self.overflow == OverflowPolicy::Block {
+            self.sender.send(msg);
+            return true;
+        }
+
+        self.dropped += 1;
+        match self.overflow {
+            OverflowPolicy::DropOldest => {
+                self.receiver.try_recv();
+                self.sender.send(msg);
+                true
+            }
+            OverflowPolicy::Disconnect => false,
+            _ => true,
+        }
+    }
+}
+
+struct Topic<M> {
+    subscribers: FnvHashMap<String, Subscriber<M>>,
+}
+
+impl<M> Topic<M> {
+    /// Removes subscribers whose `Subscription` has been dropped.
+    fn prune(&mut self) {
+        self.subscribers.retain(|name, subscriber| {
+            let alive = subscriber.alive.upgrade().is_some();
+            if !alive {
+                info!(target: "notify", "Prune disconnected {} subscriber {:?}", type_name::<M>(), name);
+            }
+            alive
+        });
+    }
+}
+
+impl<M> Default for Topic<M> {
+    fn default() -> Self {
+        Topic {
+            subscribers: FnvHashMap::default(),
+        }
+    }
+}
+
+/// Topics keyed by the message type, created on the first subscription.
+#[derive(Default)]
+struct Topics {
+    inner: FnvHashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl Topics {
+    fn topic_mut_if_exists<M: Message>(&mut self) -> Option<&mut Topic<M>> {
+        self.inner
+            .get_mut(&TypeId::of::<M>())
+            .and_then(|topic| topic.downcast_mut::<Topic<M>>())
+    }
+
+    fn topic_mut<M: Message>(&mut self) -> &mut Topic<M> {
+        self.inner
+            .entry(TypeId::of::<M>())
+            .or_insert_with(|| Box::new(Topic::<M>::default()))
+            .downcast_mut::<Topic<M>>()
+            .expect("topic is keyed by its message type")
+    }
+}
 
 #[derive(Default)]
 pub struct NotifyService {}
@@ -29,28 +316,16 @@ pub struct NotifyService {}
 #[derive(Clone)]
 pub struct NotifyController {
     signal: Sender<StopSignal>,
-    new_transaction_register: NotifyRegister<MsgNewTransaction>,
-    new_tip_register: NotifyRegister<MsgNewTip>,
-    switch_fork_register: NotifyRegister<MsgSwitchFork>,
-    new_transaction_notifier: Sender<MsgNewTransaction>,
-    new_tip_notifier: Sender<MsgNewTip>,
-    switch_fork_notifier: Sender<MsgSwitchFork>,
+    command_sender: Sender<Box<dyn Command>>,
 }
 
 impl NotifyService {
     pub fn start<S: ToString>(self, thread_name: Option<S>) -> (JoinHandle<()>, NotifyController) {
-        let (signal_sender, signal_receiver) = channel::bounded::<()>(1);
-        let (new_transaction_register, new_transaction_register_receiver) = channel::bounded(2);
-        let (new_tip_register, new_tip_register_receiver) = channel::bounded(2);
-        let (switch_fork_register, switch_fork_register_receiver) = channel::bounded(2);
-        let (new_transaction_sender, new_transaction_receiver) =
-            channel::bounded::<MsgNewTransaction>(128);
-        let (new_tip_sender, new_tip_receiver) = channel::bounded::<MsgNewTip>(128);
-        let (switch_fork_sender, switch_fork_receiver) = channel::bounded::<MsgSwitchFork>(128);
-
-        let mut new_transaction_subscribers = FnvHashMap::default();
-        let mut new_tip_subscribers = FnvHashMap::default();
-        let mut switch_fork_subscribers = FnvHashMap::default();
+        let (signal_sender, signal_receiver) = channel::bounded::<StopSignal>(1);
+        let (command_sender, command_receiver) =
+            channel::bounded::<Box<dyn Command>>(DEFAULT_CHANNEL_SIZE);
+
+        let mut topics = Topics::default();
 
         let mut thread_builder = thread::Builder::new();
         // Mainly for test: give a empty thread_name
@@ -64,189 +339,138 @@ impl NotifyService {
                         break;
                     }
 
-                    recv(new_transaction_register_receiver, msg) => Self::handle_register_new_transaction(
-                        &mut new_transaction_subscribers, msg
-                    ),
-                    recv(new_tip_register_receiver, msg) => Self::handle_register_new_tip(
-                        &mut new_tip_subscribers, msg
-                    ),
-                    recv(switch_fork_register_receiver, msg) => Self::handle_register_switch_fork(
-                        &mut switch_fork_subscribers, msg
-                    ),
-
-                    recv(new_transaction_receiver, msg) => Self::handle_notify_new_transaction(
-                        &new_transaction_subscribers, msg
-                    ),
-                    recv(new_tip_receiver, msg) => Self::handle_notify_new_tip(
-                        &new_tip_subscribers, msg
-                    ),
-                    recv(switch_fork_receiver, msg) => Self::handle_notify_switch_fork(
-                        &switch_fork_subscribers, msg
-                    )
+                    recv(command_receiver, msg) => match msg {
+                        Some(command) => command.execute(&mut topics),
+                        None => warn!(target: "notify", "command channel is closed"),
+                    }
                 }
             }).expect("Start notify service failed");
 
         (
             join_handle,
             NotifyController {
-                new_transaction_register,
-                new_tip_register,
-                switch_fork_register,
-                new_transaction_notifier: new_transaction_sender,
-                new_tip_notifier: new_tip_sender,
-                switch_fork_notifier: switch_fork_sender,
+                command_sender,
                 signal: signal_sender,
             },
         )
     }
+}
 
-    fn handle_register_new_transaction(
-        subscribers: &mut FnvHashMap<String, Sender<MsgNewTransaction>>,
-        msg: Option<Request<(String, usize), Receiver<MsgNewTransaction>>>,
-    ) {
-        match msg {
-            Some(Request {
-                responsor,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register new_transaction {:?}", name);
-                let (sender, receiver) = channel::bounded::<MsgNewTransaction>(capacity);
-                subscribers.insert(name, sender);
-                responsor.send(receiver);
-            }
-            None => warn!(target: "notify", "Register new_transaction channel is closed"),
-        }
+impl NotifyController {
+    pub fn stop(self) {
+        self.signal.send(());
     }
 
-    fn handle_register_new_tip(
-        subscribers: &mut FnvHashMap<String, Sender<MsgNewTip>>,
-        msg: Option<Request<(String, usize), Receiver<MsgNewTip>>>,
-    ) {
-        match msg {
-            Some(Request {
-                responsor,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register new_tip {:?}", name);
-                let (sender, receiver) = channel::bounded::<MsgNewTip>(capacity);
-                subscribers.insert(name, sender);
-                responsor.send(receiver);
-            }
-            None => warn!(target: "notify", "Register new_tip channel is closed"),
-        }
+    /// Subscribes to the topic of message type `M` with the default options.
+    pub fn subscribe<M: Message, S: ToString>(&self, name: S) -> Subscription<M> {
+        self.subscribe_with(name, SubscribeOptions::default())
     }
 
-    fn handle_register_switch_fork(
-        subscribers: &mut FnvHashMap<String, Sender<MsgSwitchFork>>,
-        msg: Option<Request<(String, usize), Receiver<MsgSwitchFork>>>,
-    ) {
-        match msg {
-            Some(Request {
-                responsor,
-                arguments: (name, capacity),
-            }) => {
-                debug!(target: "notify", "Register switch_fork {:?}", name);
-                let (sender, receiver) = channel::bounded::<MsgSwitchFork>(capacity);
-                subscribers.insert(name, sender);
-                responsor.send(receiver);
-            }
-            None => warn!(target: "notify", "Register switch_fork channel is closed"),
-        }
+    /// Subscribes to the topic of message type `M`, a subscriber with the same name is replaced.
+    pub fn subscribe_with<M: Message, S: ToString>(
+        &self,
+        name: S,
+        options: SubscribeOptions,
+    ) -> Subscription<M> {
+        assert!(
+            options.capacity > 0 || options.overflow == OverflowPolicy::Block,
+            "Only the block policy supports zero capacity"
+        );
+        let (responsor, response) = channel::bounded(1);
+        let request: Subscribe<M> = Request {
+            responsor,
+            arguments: (name.to_string(), options),
+        };
+        self.command_sender.send(Box::new(request));
+        // Ensure the subscriber is registered.
+        response.recv().expect("Subscribe failed")
     }
 
-    fn handle_notify_new_transaction(
-        subscribers: &FnvHashMap<String, Sender<MsgNewTransaction>>,
-        msg: Option<MsgNewTransaction>,
-    ) {
-        match msg {
-            Some(()) => {
-                trace!(target: "notify", "event new transaction {:?}", msg);
-                for subscriber in subscribers.values() {
-                    subscriber.send(());
-                }
-            }
-            None => warn!(target: "notify", "new transaction channel is closed"),
-        }
+    /// Removes the subscriber `name` from the topic of message type `M`, returns whether it
+    /// was subscribed. The subscriber receives the pending messages and then `None`.
+    pub fn unsubscribe<M: Message, S: ToString>(&self, name: S) -> bool {
+        let (responsor, response) = channel::bounded(1);
+        let request = Unsubscribe::<M> {
+            request: Request {
+                responsor,
+                arguments: name.to_string(),
+            },
+            topic: PhantomData,
+        };
+        self.command_sender.send(Box::new(request));
+        response.recv().expect("Unsubscribe failed")
     }
 
-    fn handle_notify_new_tip(
-        subscribers: &FnvHashMap<String, Sender<MsgNewTip>>,
-        msg: Option<MsgNewTip>,
-    ) {
-        match msg {
-            Some(msg) => {
-                trace!(target: "notify", "event new tip {:?}", msg);
-                for subscriber in subscribers.values() {
-                    subscriber.send(Arc::clone(&msg));
-                }
-            }
-            None => warn!(target: "notify", "new tip channel is closed"),
-        }
+    /// Returns the number of messages dropped for the subscriber `name` because its channel was
+    /// full, or `None` if it is not subscribed to the topic of message type `M`.
+    pub fn dropped_messages<M: Message, S: ToString>(&self, name: S) -> Option<u64> {
+        let (responsor, response) = channel::bounded(1);
+        let request = DroppedMessages::<M> {
+            request: Request {
+                responsor,
+                arguments: name.to_string(),
+            },
+            topic: PhantomData,
+        };
+        self.command_sender.send(Box::new(request));
+        response.recv().expect("Query dropped messages failed")
     }
 
-    fn handle_notify_switch_fork(
-        subscribers: &FnvHashMap<String, Sender<MsgSwitchFork>>,
-        msg: Option<MsgSwitchFork>,
-    ) {
-        match msg {
-            Some(msg) => {
-                trace!(target: "notify", "event switch fork {:?}", msg);
-                for subscriber in subscribers.values() {
-                    subscriber.send(Arc::clone(&msg));
-                }
-            }
-            None => warn!(target: "notify", "event 3 channel is closed"),
-        }
+    /// Sends `msg` to all subscribers of the topic of message type `M`.
+    pub fn notify<M: Message>(&self, msg: M) {
+        self.command_sender.send(Box::new(Publish(msg)));
     }
-}
 
-impl NotifyController {
-    pub fn stop(self) {
-        self.signal.send(());
+    pub fn subscribe_new_transaction<S: ToString>(
+        &self,
+        name: S,
+    ) -> Subscription<MsgNewTransaction> {
+        self.subscribe(name)
+    }
+    pub fn subscribe_new_tip<S: ToString>(&self, name: S) -> Subscription<MsgNewTip> {
+        self.subscribe(name)
+    }
+    pub fn subscribe_switch_fork<S: ToString>(&self, name: S) -> Subscription<MsgSwitchFork> {
+        self.subscribe(name)
+    }
+    pub fn subscribe_evicted_transactions<S: ToString>(
+        &self,
+        name: S,
+    ) -> Subscription<MsgEvictedTransactions> {
+        self.subscribe(name)
     }
 
-    pub fn subscribe_new_transaction<S: ToString>(&self, name: S) -> Receiver<MsgNewTransaction> {
-        let (responsor, response) = channel::bounded(1);
-        self.new_transaction_register.send(Request {
-            responsor,
-            arguments: (name.to_string(), 128),
-        });
-        // Ensure the subscriber is registered.
-        response.recv().expect("Subscribe new transaction failed")
+    pub fn unsubscribe_new_transaction<S: ToString>(&self, name: S) -> bool {
+        self.unsubscribe::<MsgNewTransaction, _>(name)
     }
-    pub fn subscribe_new_tip<S: ToString>(&self, name: S) -> Receiver<MsgNewTip> {
-        let (responsor, response) = channel::bounded(1);
-        self.new_tip_register.send(Request {
-            responsor,
-            arguments: (name.to_string(), 128),
-        });
-        // Ensure the subscriber is registered.
-        response.recv().expect("Subscribe new tip failed")
+    pub fn unsubscribe_new_tip<S: ToString>(&self, name: S) -> bool {
+        self.unsubscribe::<MsgNewTip, _>(name)
     }
-    pub fn subscribe_switch_fork<S: ToString>(&self, name: S) -> Receiver<MsgSwitchFork> {
-        let (responsor, response) = channel::bounded(1);
-        self.switch_fork_register.send(Request {
-            responsor,
-            arguments: (name.to_string(), 128),
-        });
-        // Ensure the subscriber is registered.
-        response.recv().expect("Subscribe switch fork failed")
+    pub fn unsubscribe_switch_fork<S: ToString>(&self, name: S) -> bool {
+        self.unsubscribe::<MsgSwitchFork, _>(name)
+    }
+    pub fn unsubscribe_evicted_transactions<S: ToString>(&self, name: S) -> bool {
+        self.unsubscribe::<MsgEvictedTransactions, _>(name)
     }
 
     pub fn notify_new_transaction(&self) {
-        self.new_transaction_notifier.send(());
+        self.notify::<MsgNewTransaction>(());
     }
     pub fn notify_new_tip(&self, block: MsgNewTip) {
-        self.new_tip_notifier.send(block);
+        self.notify(block);
     }
     pub fn notify_switch_fork(&self, txs: MsgSwitchFork) {
-        self.switch_fork_notifier.send(txs);
+        self.notify(txs);
+    }
+    pub fn notify_evicted_transactions(&self, txs: MsgEvictedTransactions) {
+        self.notify(txs);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use util::{CellInput, OutPoint, Transaction, H256};
 
     #[test]
     fn test_new_transaction() {
@@ -287,4 +511,124 @@ mod tests {
         notify.stop();
         handle.join().expect("join failed");
     }
+
+    #[test]
+    fn test_fork_transactions() {
+        let cellbase = IndexedTransaction::new(Transaction {
+            inputs: vec![CellInput::new_cellbase()],
+            ..Transaction::default()
+        });
+        let tx = IndexedTransaction::new(Transaction {
+            inputs: vec![CellInput::new(OutPoint { hash: H256::default(), index: 0 }, vec![])],
+            ..Transaction::default()
+        });
+        let block = |transactions: Vec<IndexedTransaction>| {
+            IndexedBlock::new(Header::default(), vec![], transactions, vec![])
+        };
+        let fork = ForkBlocks {
+            ancestor: Header::default(),
+            detached_blocks: vec![
+                block(vec![cellbase.clone(), tx.clone()]),
+                block(vec![cellbase.clone(), tx.clone()]),
+            ],
+            attached_blocks: vec![block(vec![cellbase.clone()])],
+        };
+        assert_eq!(fork.detached_transactions(), vec![&tx, &tx]);
+        assert!(fork.attached_transactions().is_empty());
+
+        // Transactions committed again in the new branch stay out of the pool
+        let fork = ForkBlocks {
+            attached_blocks: vec![block(vec![cellbase.clone(), tx.clone()])],
+            ..fork
+        };
+        assert!(fork.detached_transactions().is_empty());
+        assert_eq!(fork.attached_transactions(), vec![&tx]);
+    }
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct BlockRejected(u64);
+
+    #[test]
+    fn test_custom_topic() {
+        let (handle, notify) = NotifyService::default().start::<&str>(None);
+        let rejected = notify.subscribe::<BlockRejected, _>("relayer");
+        let tip = notify.subscribe_new_tip("relayer");
+        notify.notify(BlockRejected(1));
+        notify.notify(BlockRejected(2));
+        assert_eq!(rejected.recv(), Some(BlockRejected(1)));
+        assert_eq!(rejected.recv(), Some(BlockRejected(2)));
+        assert_eq!(tip.try_recv(), None);
+        notify.stop();
+        handle.join().expect("join failed");
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let (handle, notify) = NotifyService::default().start::<&str>(None);
+        let receiver = notify.subscribe_new_transaction("miner1");
+        notify.notify_new_transaction();
+        assert!(notify.unsubscribe_new_transaction("miner1"));
+        assert!(!notify.unsubscribe_new_transaction("miner1"));
+        notify.notify_new_transaction();
+        assert_eq!(receiver.recv(), Some(()));
+        assert_eq!(receiver.recv(), None);
+        notify.stop();
+        handle.join().expect("join failed");
+    }
+
+    #[test]
+    fn test_prune_dropped_subscriber() {
+        let (handle, notify) = NotifyService::default().start::<&str>(None);
+        let receiver = notify.subscribe_new_tip("miner1");
+        drop(notify.subscribe_new_tip("miner2"));
+        // The dropped subscriber would block the notify thread once its channel is full.
+        for _ in 0..DEFAULT_CHANNEL_SIZE + 1 {
+            notify.notify_new_tip(Arc::new(IndexedBlock::default()));
+            select! {
+                recv(receiver, msg) => assert!(msg.is_some()),
+            }
+        }
+        assert!(!notify.unsubscribe_new_tip("miner2"));
+        notify.stop();
+        handle.join().expect("join failed");
+    }
+
+    fn subscribe_overflow(notify: &NotifyController, overflow: OverflowPolicy) -> Subscription<u64> {
+        notify.subscribe_with("miner1", SubscribeOptions { capacity: 2, overflow })
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let (handle, notify) = NotifyService::default().start::<&str>(None);
+
+        let receiver = subscribe_overflow(&notify, OverflowPolicy::DropNewest);
+        for i in 0..4u64 {
+            notify.notify(i);
+        }
+        assert_eq!(notify.dropped_messages::<u64, _>("miner1"), Some(2));
+        assert_eq!(receiver.try_recv(), Some(0));
+        assert_eq!(receiver.try_recv(), Some(1));
+        assert_eq!(receiver.try_recv(), None);
+
+        let receiver = subscribe_overflow(&notify, OverflowPolicy::DropOldest);
+        for i in 0..4u64 {
+            notify.notify(i);
+        }
+        assert_eq!(notify.dropped_messages::<u64, _>("miner1"), Some(2));
+        assert_eq!(receiver.try_recv(), Some(2));
+        assert_eq!(receiver.try_recv(), Some(3));
+        assert_eq!(receiver.try_recv(), None);
+
+        let receiver = subscribe_overflow(&notify, OverflowPolicy::Disconnect);
+        for i in 0..4u64 {
+            notify.notify(i);
+        }
+        assert_eq!(notify.dropped_messages::<u64, _>("miner1"), None);
+        assert_eq!(receiver.recv(), Some(0));
+        assert_eq!(receiver.recv(), Some(1));
+        assert_eq!(receiver.recv(), None);
+
+        notify.stop();
+        handle.join().expect("join failed");
+    }
 }
@@ -0,0 +1,176 @@
+use fnv::{FnvHashMap, FnvHashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use services::orphan_pool::OrphanPool;
+use util::{IndexedBlock, H256};
+
+pub const DEFAULT_MAX_ORPHAN_BLOCKS: usize = 1024;
+pub const DEFAULT_MAX_ORPHAN_BLOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Blocks whose parent is unknown yet, keyed by the parent hash.
+pub struct OrphanBlockPool {
+    blocks: OrphanPool<Arc<IndexedBlock>>,
+    /// parent hash -> hashes of the blocks waiting for it
+    children: FnvHashMap<H256, FnvHashSet<H256>>,
+}
+
+impl Default for OrphanBlockPool {
+    fn default() -> Self {
+        OrphanBlockPool::new(DEFAULT_MAX_ORPHAN_BLOCKS, DEFAULT_MAX_ORPHAN_BLOCK_AGE)
+    }
+}
+
+impl OrphanBlockPool {
+    pub fn new(max_size: usize, max_age: Duration) -> Self {
+        OrphanBlockPool {
+            blocks: OrphanPool::new(max_size, max_age),
+            children: FnvHashMap::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.blocks.contains(hash)
+    }
+
+    /// Adds the block, within the limits of `OrphanPool`.
+    pub fn insert(&mut self, block: Arc<IndexedBlock>) {
+        self.insert_at(block, Instant::now());
+    }
+
+    fn insert_at(&mut self, block: Arc<IndexedBlock>, now: Instant) {
+        let hash = block.hash();
+        let parent_hash = block.header().parent_hash;
+        for evicted in self.blocks.insert(hash, block, now) {
+            self.unlink(&evicted);
+        }
+        if self.contains(&hash) {
+            self.children.entry(parent_hash).or_default().insert(hash);
+        }
+    }
+
+    /// Removes and returns all the descendants of `parent_hash`, every block after its parent.
+    ///
+    /// Expired blocks are dropped first, so they and their descendants are not returned.
+    pub fn remove_descendants(&mut self, parent_hash: &H256) -> Vec<Arc<IndexedBlock>> {
+        self.remove_descendants_at(parent_hash, Instant::now())
+    }
+
+    fn remove_descendants_at(
+        &mut self,
+        parent_hash: &H256,
+        now: Instant,
+    ) -> Vec<Arc<IndexedBlock>> {
+        for expired in self.blocks.remove_expired(now) {
+            self.unlink(&expired);
+        }
+        let mut descendants = Vec::new();
+        let mut parents = vec![*parent_hash];
+        while let Some(parent_hash) = parents.pop() {
+            for hash in self.children.remove(&parent_hash).unwrap_or_default() {
+                if let Some(block) = self.blocks.remove(&hash) {
+                    parents.push(hash);
+                    descendants.push(block);
+                }
+            }
+        }
+        descendants
+    }
+
+    /// The parents to request, which are the roots of the orphan chains.
+    pub fn missing_parents(&self) -> Vec<H256> {
+        self.children
+            .keys()
+            .filter(|hash| !self.blocks.contains(hash))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes the block from the children of its parent.
+    fn unlink(&mut self, block: &IndexedBlock) {
+        let parent_hash = block.header().parent_hash;
+        let is_empty = match self.children.get_mut(&parent_hash) {
+            Some(hashes) => {
+                hashes.remove(&block.hash());
+                hashes.is_empty()
+            }
+            None => false,
+        };
+        if is_empty {
+            self.children.remove(&parent_hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::Header;
+
+    fn block(parent_hash: H256, number: u64) -> Arc<IndexedBlock> {
+        let header = Header {
+            parent_hash,
+            number,
+            ..Header::default()
+        };
+        Arc::new(IndexedBlock::new(header, vec![], vec![], vec![]))
+    }
+
+    #[test]
+    fn test_descendants() {
+        let mut pool = OrphanBlockPool::default();
+        let missing = H256::from([1; 32]);
+        let b1 = block(missing, 1);
+        let b2 = block(b1.hash(), 2);
+        let b3 = block(b2.hash(), 3);
+        let other = block(H256::from([2; 32]), 1);
+        for block in &[&b3, &b2, &b1, &other] {
+            pool.insert(Arc::clone(block));
+        }
+        assert_eq!(pool.len(), 4);
+
+        let mut missing_parents = pool.missing_parents();
+        missing_parents.sort();
+        assert_eq!(missing_parents, vec![missing, other.header().parent_hash]);
+
+        assert_eq!(pool.remove_descendants(&missing), vec![b1, b2, b3]);
+        assert_eq!(pool.missing_parents(), vec![other.header().parent_hash]);
+        assert!(pool.contains(&other.hash()));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_expired() {
+        let mut pool = OrphanBlockPool::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        let missing = H256::from([1; 32]);
+        let b1 = block(missing, 1);
+        let b2 = block(b1.hash(), 2);
+        let other = block(missing, 3);
+        pool.insert_at(Arc::clone(&b1), now);
+        pool.insert_at(Arc::clone(&b2), now + Duration::from_secs(5));
+        // Full, the oldest block is evicted and its child waits for it
+        pool.insert_at(Arc::clone(&other), now + Duration::from_secs(6));
+        assert!(!pool.contains(&b1.hash()));
+        let mut missing_parents = pool.missing_parents();
+        missing_parents.sort();
+        let mut expected = vec![missing, b1.hash()];
+        expected.sort();
+        assert_eq!(missing_parents, expected);
+
+        // Expired blocks are dropped instead of being connected
+        let later = now + Duration::from_millis(15_500);
+        assert_eq!(pool.remove_descendants_at(&b1.hash(), later), vec![]);
+        assert_eq!(pool.remove_descendants_at(&missing, later), vec![other]);
+        assert!(pool.is_empty());
+        assert!(pool.missing_parents().is_empty());
+    }
+}
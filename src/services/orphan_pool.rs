@@ -0,0 +1,123 @@
+use fnv::FnvHashMap;
+use std::time::{Duration, Instant};
+
+use util::H256;
+
+struct Orphan<T> {
+    item: T,
+    received_at: Instant,
+}
+
+/// Items waiting for something unknown yet, keyed by hash, limited in number and age.
+///
+/// `OrphanBlockPool` and `OrphanTransactionPool` index the items by what they wait for, so the
+/// items dropped by the pool are returned to them.
+pub struct OrphanPool<T> {
+    orphans: FnvHashMap<H256, Orphan<T>>,
+    max_size: usize,
+    max_age: Duration,
+}
+
+impl<T> OrphanPool<T> {
+    pub fn new(max_size: usize, max_age: Duration) -> Self {
+        OrphanPool {
+            orphans: FnvHashMap::default(),
+            max_size,
+            max_age,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    pub fn received_at(&self, hash: &H256) -> Option<Instant> {
+        self.orphans.get(hash).map(|orphan| orphan.received_at)
+    }
+
+    /// Adds the item received at `now`, evicting the expired items first and then the oldest
+    /// ones when the pool is full, and returns the evicted items.
+    ///
+    /// Nothing is added if the item is already in the pool, or if `max_size` is 0.
+    pub fn insert(&mut self, hash: H256, item: T, now: Instant) -> Vec<T> {
+        if self.max_size == 0 || self.contains(&hash) {
+            return Vec::new();
+        }
+        let mut evicted = self.remove_expired(now);
+        while self.len() >= self.max_size {
+            evicted.extend(self.remove_oldest());
+        }
+        self.orphans.insert(
+            hash,
+            Orphan {
+                item,
+                received_at: now,
+            },
+        );
+        evicted
+    }
+
+    pub fn remove(&mut self, hash: &H256) -> Option<T> {
+        self.orphans.remove(hash).map(|orphan| orphan.item)
+    }
+
+    /// Removes and returns the items older than `max_age` at `now`.
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<T> {
+        let max_age = self.max_age;
+        let expired = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.duration_since(orphan.received_at) > max_age)
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        expired.iter().filter_map(|hash| self.remove(hash)).collect()
+    }
+
+    fn remove_oldest(&mut self) -> Option<T> {
+        let oldest = self
+            .orphans
+            .iter()
+            .min_by_key(|(_, orphan)| orphan.received_at)
+            .map(|(hash, _)| *hash)?;
+        self.remove(&oldest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let mut pool = OrphanPool::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        let hashes = (0..4).map(|index| H256::from([index; 32])).collect::<Vec<_>>();
+
+        assert_eq!(pool.insert(hashes[0], 0, now), vec![]);
+        assert_eq!(pool.insert(hashes[1], 1, now + Duration::from_secs(1)), vec![]);
+        assert_eq!(pool.insert(hashes[1], 1, now + Duration::from_secs(1)), vec![]);
+        // Full, the oldest item is evicted
+        assert_eq!(pool.insert(hashes[2], 2, now + Duration::from_secs(2)), vec![0]);
+        assert!(!pool.contains(&hashes[0]));
+        assert_eq!(pool.len(), 2);
+
+        // Expired items are evicted first
+        assert_eq!(pool.insert(hashes[3], 3, now + Duration::from_secs(12)), vec![1]);
+        assert!(pool.contains(&hashes[2]));
+        assert!(pool.contains(&hashes[3]));
+        assert_eq!(pool.remove_expired(now + Duration::from_secs(13)), vec![2]);
+        assert_eq!(pool.len(), 1);
+
+        let mut disabled = OrphanPool::new(0, Duration::from_secs(10));
+        assert_eq!(disabled.insert(hashes[0], 0, now), vec![]);
+        assert!(disabled.is_empty());
+    }
+}
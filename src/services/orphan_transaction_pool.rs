@@ -0,0 +1,191 @@
+use fnv::{FnvHashMap, FnvHashSet};
+use std::time::{Duration, Instant};
+
+use services::orphan_pool::OrphanPool;
+use util::{IndexedTransaction, OutPoint, H256};
+
+pub const DEFAULT_MAX_ORPHAN_TRANSACTIONS: usize = 1024;
+pub const DEFAULT_MAX_ORPHAN_TRANSACTION_AGE: Duration = Duration::from_secs(20 * 60);
+
+struct OrphanTransaction {
+    transaction: IndexedTransaction,
+    /// The outputs spent or read by the transaction which are unknown
+    missing: Vec<OutPoint>,
+}
+
+/// Transactions spending or reading outputs unknown yet, keyed by the missing outputs.
+pub struct OrphanTransactionPool {
+    transactions: OrphanPool<OrphanTransaction>,
+    /// missing output -> hashes of the transactions waiting for it
+    waiting: FnvHashMap<OutPoint, FnvHashSet<H256>>,
+}
+
+impl Default for OrphanTransactionPool {
+    fn default() -> Self {
+        OrphanTransactionPool::new(
+            DEFAULT_MAX_ORPHAN_TRANSACTIONS,
+            DEFAULT_MAX_ORPHAN_TRANSACTION_AGE,
+        )
+    }
+}
+
+impl OrphanTransactionPool {
+    pub fn new(max_size: usize, max_age: Duration) -> Self {
+        OrphanTransactionPool {
+            transactions: OrphanPool::new(max_size, max_age),
+            waiting: FnvHashMap::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.transactions.contains(hash)
+    }
+
+    /// Adds the transaction waiting for the `missing` outputs, within the limits of
+    /// `OrphanPool`.
+    pub fn insert(&mut self, transaction: IndexedTransaction, missing: Vec<OutPoint>) {
+        self.insert_at(transaction, missing, Instant::now());
+    }
+
+    fn insert_at(&mut self, transaction: IndexedTransaction, missing: Vec<OutPoint>, now: Instant) {
+        let hash = transaction.hash();
+        if self.contains(&hash) {
+            return;
+        }
+        let orphan = OrphanTransaction {
+            transaction,
+            missing: missing.clone(),
+        };
+        for evicted in self.transactions.insert(hash, orphan, now) {
+            self.unlink(&evicted);
+        }
+        if self.contains(&hash) {
+            for out_point in missing {
+                self.waiting.entry(out_point).or_default().insert(hash);
+            }
+        }
+    }
+
+    /// Removes and returns the transactions waiting for any of the outputs, oldest first.
+    ///
+    /// They may still miss other outputs, and should be added again to find out. Expired
+    /// transactions are dropped first, so they are not returned.
+    pub fn remove_waiting_for<I>(&mut self, out_points: I) -> Vec<IndexedTransaction>
+    where
+        I: IntoIterator<Item = OutPoint>,
+    {
+        self.remove_waiting_for_at(out_points, Instant::now())
+    }
+
+    fn remove_waiting_for_at<I>(&mut self, out_points: I, now: Instant) -> Vec<IndexedTransaction>
+    where
+        I: IntoIterator<Item = OutPoint>,
+    {
+        for expired in self.transactions.remove_expired(now) {
+            self.unlink(&expired);
+        }
+        let mut hashes = out_points
+            .into_iter()
+            .filter_map(|out_point| self.waiting.get(&out_point))
+            .flat_map(|hashes| hashes.iter().cloned())
+            .collect::<FnvHashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        hashes.sort_by_key(|hash| (self.transactions.received_at(hash), *hash));
+        let mut transactions = Vec::new();
+        for hash in hashes {
+            if let Some(orphan) = self.transactions.remove(&hash) {
+                self.unlink(&orphan);
+                transactions.push(orphan.transaction);
+            }
+        }
+        transactions
+    }
+
+    /// Removes the transaction from the ones waiting for its missing outputs.
+    fn unlink(&mut self, orphan: &OrphanTransaction) {
+        let hash = orphan.transaction.hash();
+        for out_point in &orphan.missing {
+            let is_empty = match self.waiting.get_mut(out_point) {
+                Some(hashes) => {
+                    hashes.remove(&hash);
+                    hashes.is_empty()
+                }
+                None => false,
+            };
+            if is_empty {
+                self.waiting.remove(out_point);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{CellInput, Transaction};
+
+    fn out_point(index: u32) -> OutPoint {
+        OutPoint {
+            hash: H256::from([1; 32]),
+            index,
+        }
+    }
+
+    fn transaction(inputs: Vec<OutPoint>) -> IndexedTransaction {
+        Transaction {
+            inputs: inputs
+                .into_iter()
+                .map(|out_point| CellInput::new(out_point, vec![]))
+                .collect(),
+            ..Transaction::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_waiting() {
+        let mut pool = OrphanTransactionPool::default();
+        let now = Instant::now();
+        let tx1 = transaction(vec![out_point(0), out_point(1)]);
+        let tx2 = transaction(vec![out_point(1)]);
+        let tx3 = transaction(vec![out_point(2)]);
+        pool.insert_at(tx1.clone(), vec![out_point(0), out_point(1)], now);
+        pool.insert_at(tx2.clone(), vec![out_point(1)], now + Duration::from_secs(1));
+        pool.insert_at(tx3.clone(), vec![out_point(2)], now + Duration::from_secs(2));
+        assert_eq!(pool.len(), 3);
+
+        assert_eq!(pool.remove_waiting_for(vec![out_point(1)]), vec![tx1, tx2]);
+        assert_eq!(pool.remove_waiting_for(vec![out_point(0)]), vec![]);
+        assert!(pool.contains(&tx3.hash()));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_expired() {
+        let mut pool = OrphanTransactionPool::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        let txs = (0..3).map(|index| transaction(vec![out_point(index)])).collect::<Vec<_>>();
+        pool.insert_at(txs[0].clone(), vec![out_point(0)], now);
+        pool.insert_at(txs[1].clone(), vec![out_point(1)], now + Duration::from_secs(5));
+        // Full, the oldest transaction is evicted and no longer waits
+        pool.insert_at(txs[2].clone(), vec![out_point(2)], now + Duration::from_secs(6));
+        assert!(!pool.contains(&txs[0].hash()));
+        assert!(!pool.waiting.contains_key(&out_point(0)));
+
+        // Expired transactions are dropped instead of being promoted
+        let out_points = vec![out_point(1), out_point(2)];
+        let later = now + Duration::from_millis(15_500);
+        assert_eq!(pool.remove_waiting_for_at(out_points, later), vec![txs[2].clone()]);
+        assert!(pool.is_empty());
+        assert!(pool.waiting.is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+
+use std::thread;
+use std::thread::JoinHandle;
+use std::sync::Arc;
+use std::time::Duration;
+
+use channel::{self, Sender, Receiver};
+
+use util::{
+    Request,
+    Shared,
+    ChainStore,
+    IndexedTransaction,
+    InsertionResult,
+    StoreError,
+    H256,
+};
+use verification::{TransactionError, TransactionsVerifier};
+use services::orphan_transaction_pool::{
+    DEFAULT_MAX_ORPHAN_TRANSACTIONS,
+    DEFAULT_MAX_ORPHAN_TRANSACTION_AGE,
+};
+use services::notify::{
+    NotifyController,
+    Subscription,
+    MsgNewTip,
+    MsgSwitchFork,
+    EvictedTransactions,
+    TXS_POOL_SUBSCRIBER,
+};
+
+pub mod pool;
+
+use self::pool::TransactionPool;
+
+/// What to do with a transaction spending an output already spent by transactions in the pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the transactions in the pool, fails with `PoolError::DoubleSpent`
+    #[default]
+    Reject,
+    /// Evict the transactions in the pool and their descendants
+    Replace,
+    /// Replace only if the transaction pays a strictly higher fee than all the evicted
+    /// transactions together, and a strictly higher fee rate than each conflicting one
+    ReplaceByFee,
+}
+
+pub const DEFAULT_MAX_POOL_TRANSACTIONS: usize = 10_000;
+pub const DEFAULT_MAX_POOL_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct TransactionPoolConfig {
+    /// Limits the encoded size of the transactions committed in a block
+    pub max_commit_bytes: Option<usize>,
+    pub conflict_policy: ConflictPolicy,
+    /// Number of transactions in the pool, the lowest fee rates are evicted beyond
+    pub max_transactions: usize,
+    /// Encoded size of the transactions in the pool, the lowest fee rates are evicted beyond
+    pub max_bytes: usize,
+    /// Number of transactions waiting for unknown inputs, the oldest are evicted beyond
+    pub max_orphans: usize,
+    /// Time a transaction waits for unknown inputs before being evicted
+    pub max_orphan_age: Duration,
+}
+
+impl Default for TransactionPoolConfig {
+    fn default() -> Self {
+        TransactionPoolConfig {
+            max_commit_bytes: None,
+            conflict_policy: ConflictPolicy::default(),
+            max_transactions: DEFAULT_MAX_POOL_TRANSACTIONS,
+            max_bytes: DEFAULT_MAX_POOL_BYTES,
+            max_orphans: DEFAULT_MAX_ORPHAN_TRANSACTIONS,
+            max_orphan_age: DEFAULT_MAX_ORPHAN_TRANSACTION_AGE,
+        }
+    }
+}
+
+pub struct TransactionPoolService<S> {
+    shared: Shared<S>,
+    notify: NotifyController,
+    new_tip_receiver: Subscription<MsgNewTip>,
+    switch_fork_receiver: Subscription<MsgSwitchFork>,
+    pool: TransactionPool<S>,
+}
+
+impl<S: ChainStore> TransactionPoolService<S> {
+    pub fn new(
+        shared: Shared<S>,
+        notify: NotifyController,
+        config: TransactionPoolConfig,
+    ) -> Self {
+        let new_tip_receiver = notify.subscribe_new_tip(TXS_POOL_SUBSCRIBER);
+        let switch_fork_receiver = notify.subscribe_switch_fork(TXS_POOL_SUBSCRIBER);
+        let pool = TransactionPool::new(
+            Arc::clone(&shared.store),
+            shared.tip_header().number(),
+            &shared.consensus,
+            config,
+        );
+        TransactionPoolService {
+            shared,
+            notify,
+            new_tip_receiver,
+            switch_fork_receiver,
+            pool,
+        }
+    }
+}
+
+/// The transactions to propose and to commit in a block.
+pub type ProposalCommitTxs = (Vec<IndexedTransaction>, Vec<IndexedTransaction>);
+
+#[derive(Clone)]
+pub struct TransactionPoolController {
+    proposal_commit_txs_sender: Sender<Request<(usize, usize), ProposalCommitTxs>>,
+    add_transaction_sender: Sender<Request<IndexedTransaction, Result<InsertionResult, PoolError>>>,
+}
+
+pub struct TransactionPoolReceivers {
+    proposal_commit_txs_receiver: Receiver<Request<(usize, usize), ProposalCommitTxs>>,
+    add_transaction_receiver: Receiver<Request<IndexedTransaction, Result<InsertionResult, PoolError>>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PoolError {
+    /// An invalid pool entry caused by underlying tx validation error
+    InvalidTx(TransactionError),
+    /// An entry already in the pool
+    AlreadyInPool,
+    /// A double spend of an output spent by this transaction, in the pool or in the chain
+    DoubleSpent(H256),
+    /// Transaction pool is over capacity, can't accept more transactions
+    OverCapacity,
+    /// A duplicate output, the transaction is already in the chain
+    DuplicateOutput,
+    /// Coinbase transaction
+    CellBase,
+    /// TimeOut
+    TimeOut,
+    /// Blocknumber is not right
+    InvalidBlockNumber,
+    /// Failed to read the chain
+    Store(StoreError),
+}
+
+impl From<StoreError> for PoolError {
+    fn from(err: StoreError) -> Self {
+        PoolError::Store(err)
+    }
+}
+
+impl TransactionPoolController {
+    pub fn new() -> (TransactionPoolController, TransactionPoolReceivers) {
+        let (proposal_commit_txs_sender, proposal_commit_txs_receiver) = channel::bounded(32);
+        let (add_transaction_sender, add_transaction_receiver) = channel::bounded(64);
+        (
+            TransactionPoolController {
+                proposal_commit_txs_sender,
+                add_transaction_sender,
+            },
+            TransactionPoolReceivers {
+                proposal_commit_txs_receiver,
+                add_transaction_receiver,
+            }
+        )
+    }
+
+    pub fn get_proposal_commit_txs(
+        &self,
+        max_prop: usize,
+        max_tx: usize
+    ) -> (Vec<IndexedTransaction>, Vec<IndexedTransaction>)
+    {
+        let (responsor, response) = channel::bounded(1);
+        self.proposal_commit_txs_sender.send(Request {
+            responsor,
+            arguments: (max_prop, max_tx),
+        });
+        response.recv().expect("get_proposal_commit_txs failed")
+    }
+
+    pub fn add_transaction(&self, tx: IndexedTransaction) -> Result<InsertionResult, PoolError> {
+        let (responsor, response) = channel::bounded(1);
+        self.add_transaction_sender.send(Request {
+            responsor,
+            arguments: tx,
+        });
+        response.recv().expect("add_transaction failed")
+    }
+}
+
+impl<S: ChainStore + 'static> TransactionPoolService<S> {
+    pub fn start(mut self, receivers: TransactionPoolReceivers) -> JoinHandle<()> {
+        thread::spawn(move || {
+            loop {
+                select! {
+                    recv(self.new_tip_receiver, msg) => match msg {
+                        Some(block) => {
+                            self.pool.reconcile_block(&block);
+                            self.notify_evicted();
+                        },
+                        None => error!("channel closed")
+                    }
+                    recv(self.switch_fork_receiver, msg) => match msg {
+                        Some(blocks) => {
+                            self.pool.switch_fork(&blocks);
+                            self.notify_evicted();
+                        },
+                        None => error!("channel closed")
+                    }
+                    recv(receivers.proposal_commit_txs_receiver, msg) => match msg {
+                        Some(Request { responsor, arguments: (max_prop, max_tx) }) => {
+                            responsor.send(self.pool.get_proposal_commit_txs(max_prop, max_tx));
+                        },
+                        None => error!("channel closed"),
+                    }
+                    recv(receivers.add_transaction_receiver, msg) => match msg {
+                        Some(Request { responsor, arguments: transaction }) => {
+                            responsor.send(self.add_transaction(transaction));
+                        },
+                        None => error!("channel closed"),
+                    }
+                }
+            }
+        })
+    }
+
+    fn add_transaction(&mut self, tx: IndexedTransaction) -> Result<InsertionResult, PoolError> {
+        let hash = tx.hash();
+        if !self.shared.verification_cache.lock().contains_transaction(&hash) {
+            TransactionsVerifier::verify_transaction(&tx).map_err(PoolError::InvalidTx)?;
+            // Block verification skips the transactions in the cache.
+            self.shared.verification_cache.lock().insert_transaction(hash);
+        }
+        let result = self.pool.add_transaction(tx);
+        self.notify_evicted();
+        result
+    }
+
+    /// Publishes the transactions evicted to keep the pool within its limits.
+    fn notify_evicted(&mut self) {
+        let evicted = self.pool.take_evicted();
+        if !evicted.is_empty() {
+            debug!(target: "txs_pool", "Evict {} transactions over capacity", evicted.len());
+            self.notify.notify_evicted_transactions(Arc::new(EvictedTransactions(evicted)));
+        }
+    }
+}
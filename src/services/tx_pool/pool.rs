@@ -0,0 +1,1309 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::mem;
+use std::sync::Arc;
+
+use fnv::{FnvHashMap, FnvHashSet};
+
+use services::notify::ForkBlocks;
+use services::orphan_transaction_pool::OrphanTransactionPool;
+use services::tx_pool::{ConflictPolicy, PoolError, TransactionPoolConfig};
+use util::codec;
+use util::{
+    BlockNumber, Capacity, CellOutput, ChainStore, Consensus, IndexedBlock, IndexedTransaction,
+    InsertionResult, OutPoint, StoreError, H256,
+};
+use verification::TransactionsVerifier;
+
+/// Where a transaction is in the two steps of getting into the chain: it must be proposed in
+/// a block first, then it can be committed `transaction_propagation_time` blocks later.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// Waiting to be proposed
+    Pending,
+    /// Proposed in the block with this number
+    Proposed(BlockNumber),
+}
+
+struct PoolEntry {
+    transaction: IndexedTransaction,
+    stage: Stage,
+    /// Inputs capacity minus outputs capacity, inputs which cannot be resolved count as 0
+    fee: Capacity,
+    /// Encoded size in bytes
+    size: usize,
+    /// Insertion order
+    sequence: u64,
+}
+
+/// The transactions of `TransactionPoolService`, indexed by hash and by the outputs they spend.
+pub struct TransactionPool<S> {
+    store: Arc<S>,
+    entries: FnvHashMap<H256, PoolEntry>,
+    /// The outputs spent by the transactions in the pool, and their spenders
+    spent: FnvHashMap<OutPoint, H256>,
+    /// The outputs read as deps by the transactions in the pool, and their readers
+    readers: FnvHashMap<OutPoint, Vec<H256>>,
+    /// Encoded size of all the entries
+    total_bytes: usize,
+    /// Evicted over capacity, until taken by `take_evicted`
+    evicted: Vec<H256>,
+    orphans: OrphanTransactionPool,
+    next_sequence: u64,
+    tip_number: BlockNumber,
+    propagation_time: BlockNumber,
+    propagation_timeout: BlockNumber,
+    config: TransactionPoolConfig,
+}
+
+impl<S: ChainStore> TransactionPool<S> {
+    pub fn new(
+        store: Arc<S>,
+        tip_number: BlockNumber,
+        consensus: &Consensus,
+        config: TransactionPoolConfig,
+    ) -> Self {
+        TransactionPool {
+            store,
+            entries: FnvHashMap::default(),
+            spent: FnvHashMap::default(),
+            readers: FnvHashMap::default(),
+            total_bytes: 0,
+            evicted: Vec::new(),
+            orphans: OrphanTransactionPool::new(config.max_orphans, config.max_orphan_age),
+            next_sequence: 0,
+            tip_number,
+            propagation_time: consensus.transaction_propagation_time,
+            propagation_timeout: consensus.transaction_propagation_timeout,
+            config,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn stage(&self, hash: &H256) -> Option<Stage> {
+        self.entries.get(hash).map(|entry| entry.stage)
+    }
+
+    /// Whether the transaction is waiting for unknown inputs.
+    pub fn contains_orphan(&self, hash: &H256) -> bool {
+        self.orphans.contains(hash)
+    }
+
+    /// Encoded size of the transactions in the pool.
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// The transactions evicted to keep the pool within its limits since the last call,
+    /// lowest fee rate first.
+    pub fn take_evicted(&mut self) -> Vec<H256> {
+        mem::take(&mut self.evicted)
+    }
+
+    /// The transaction in the pool spending the output.
+    pub fn spender(&self, out_point: &OutPoint) -> Option<H256> {
+        self.spent.get(out_point).cloned()
+    }
+
+    /// Adds a verified transaction as pending.
+    ///
+    /// A transaction spending an output already spent in the chain is rejected, one spending
+    /// an output spent in the pool is handled by `TransactionPoolConfig::conflict_policy`. The
+    /// transactions evicted by a replacement are returned in `InsertionResult::Replaced`.
+    ///
+    /// When the pool is full, the transactions with the lowest fee rate are evicted to make
+    /// room, it fails with `PoolError::OverCapacity` if the transaction would be the lowest.
+    ///
+    /// A transaction with unknown inputs or deps is kept as an orphan, and added again once
+    /// the transactions creating them are added or committed.
+    pub fn add_transaction(
+        &mut self,
+        transaction: IndexedTransaction,
+    ) -> Result<InsertionResult, PoolError> {
+        let parent = (transaction.hash(), transaction.outputs.len());
+        let result = self.add(transaction)?;
+        if result != InsertionResult::Orphan {
+            self.promote_orphans(vec![parent]);
+        }
+        Ok(result)
+    }
+
+    fn add(&mut self, transaction: IndexedTransaction) -> Result<InsertionResult, PoolError> {
+        let hash = transaction.hash();
+        if transaction.is_cellbase() {
+            return Err(PoolError::CellBase);
+        }
+        if self.contains(&hash) || self.contains_orphan(&hash) {
+            return Err(PoolError::AlreadyInPool);
+        }
+        if self.store.get_transaction_address(&hash)?.is_some() {
+            return Err(PoolError::DuplicateOutput);
+        }
+        let conflicts = self.conflicts(&transaction)?;
+        let missing = self.missing_out_points(&transaction)?;
+        if !missing.is_empty() {
+            self.orphans.insert(transaction, missing);
+            return Ok(InsertionResult::Orphan);
+        }
+        let fee = self.fee(&transaction)?;
+        let replaced = match conflicts.first() {
+            Some(&conflict) => {
+                let replaced = self.descendants(&conflicts);
+                let replace = match self.config.conflict_policy {
+                    ConflictPolicy::Reject => false,
+                    ConflictPolicy::Replace => true,
+                    ConflictPolicy::ReplaceByFee => {
+                        self.pays_for_replacement(&transaction, fee, &conflicts, &replaced)
+                    }
+                };
+                // The outputs it spends would be gone with the replaced transactions
+                if !replace || self.parents(&transaction).any(|parent| replaced.contains(&parent))
+                {
+                    return Err(PoolError::DoubleSpent(conflict));
+                }
+                replaced
+            }
+            None => Vec::new(),
+        };
+        let over_capacity = self.make_room(&transaction, fee, &replaced)?;
+
+        for hash in replaced.iter().chain(&over_capacity) {
+            self.remove(hash);
+        }
+        self.evicted.extend(over_capacity);
+        self.insert(transaction, fee);
+        if replaced.is_empty() {
+            Ok(InsertionResult::Pending)
+        } else {
+            Ok(InsertionResult::Replaced(replaced))
+        }
+    }
+
+    /// The pending transactions to propose and the transactions which can be committed in the
+    /// next block.
+    ///
+    /// Both are selected by descending fee rate of packages, a transaction together with the
+    /// ancestors in the pool it cannot go without, so a profitable child pulls its parents in
+    /// and every transaction comes after its parents. Commits also respect
+    /// `TransactionPoolConfig::max_commit_bytes`.
+    pub fn get_proposal_commit_txs(
+        &self,
+        max_prop: usize,
+        max_tx: usize,
+    ) -> (Vec<IndexedTransaction>, Vec<IndexedTransaction>) {
+        let next_number = self.tip_number + 1;
+        let propagation_time = self.propagation_time;
+        // Parents proposed earlier need not be proposed again
+        let proposals = self.select(
+            max_prop,
+            None,
+            |stage| stage == Stage::Pending,
+            |stage| stage != Stage::Pending,
+        );
+        let commits = self.select(
+            max_tx,
+            self.config.max_commit_bytes,
+            |stage| match stage {
+                Stage::Proposed(number) => number + propagation_time <= next_number,
+                Stage::Pending => false,
+            },
+            |_| false,
+        );
+        (proposals, commits)
+    }
+
+    /// Moves the transactions proposed in the new tip to the proposed stage, drops the
+    /// committed ones and the ones they conflict with, and returns the proposals which timed
+    /// out to the pending stage.
+    ///
+    /// Proposals unknown to the pool are verified and added like received transactions first,
+    /// so they may be rejected, kept as orphans or evict cheaper transactions.
+    pub fn reconcile_block(&mut self, block: &IndexedBlock) {
+        let number = block.number();
+        self.tip_number = number;
+
+        for transaction in block.proposal_transactions() {
+            let hash = transaction.hash();
+            if !self.contains(&hash) {
+                let result = TransactionsVerifier::verify_transaction(transaction)
+                    .map_err(PoolError::InvalidTx)
+                    .and_then(|()| self.add_transaction(transaction.clone()));
+                if let Err(err) = result {
+                    debug!(target: "txs_pool", "Drop proposal {}: {:?}", hash, err);
+                }
+            }
+            if let Some(entry) = self.entries.get_mut(&hash) {
+                if entry.stage == Stage::Pending {
+                    entry.stage = Stage::Proposed(number);
+                }
+            }
+        }
+        for transaction in block.commit_transactions() {
+            self.remove(&transaction.hash());
+            let conflicts = transaction
+                .inputs
+                .iter()
+                .filter_map(|input| self.spender(&input.previous_output))
+                .collect::<Vec<_>>();
+            for hash in self.descendants(&conflicts) {
+                self.remove(&hash);
+            }
+        }
+        let parents = block
+            .commit_transactions()
+            .iter()
+            .chain(block.proposal_transactions())
+            .map(|transaction| (transaction.hash(), transaction.outputs.len()))
+            .collect();
+        self.promote_orphans(parents);
+
+        let timeout = self.propagation_timeout;
+        for entry in self.entries.values_mut() {
+            if let Stage::Proposed(proposed) = entry.stage {
+                if proposed + timeout < number {
+                    entry.stage = Stage::Pending;
+                }
+            }
+        }
+        self.limit();
+    }
+
+    /// Undoes the detached blocks, their committed transactions are added again in chain
+    /// order, so parents come first, then reconciles the attached blocks.
+    ///
+    /// They were verified with their blocks, but may now conflict with the new chain or the
+    /// pool, miss inputs created only in the detached blocks, or not fit in the pool. The
+    /// transactions in the pool spending or reading the outputs of the ones which do not come
+    /// back are kept as orphans again.
+    pub fn switch_fork(&mut self, fork: &ForkBlocks) {
+        let ancestor = fork.ancestor.number;
+        self.tip_number = ancestor;
+        for entry in self.entries.values_mut() {
+            if let Stage::Proposed(number) = entry.stage {
+                if number > ancestor {
+                    entry.stage = Stage::Pending;
+                }
+            }
+        }
+        for transaction in fork.detached_transactions() {
+            let hash = transaction.hash();
+            if self.contains(&hash) {
+                continue;
+            }
+            if let Err(err) = self.add_transaction(transaction.clone()) {
+                debug!(target: "txs_pool", "Drop detached transaction {}: {:?}", hash, err);
+            }
+        }
+        for block in &fork.attached_blocks {
+            self.reconcile_block(block);
+        }
+        self.reorphan_unresolved();
+        self.limit();
+    }
+
+    /// Takes out the entries with inputs or deps which cannot be resolved anymore and their
+    /// descendants, and adds them again so they wait as orphans for the missing outputs.
+    fn reorphan_unresolved(&mut self) {
+        let mut unresolved = Vec::new();
+        for (hash, entry) in &self.entries {
+            match self.missing_out_points(&entry.transaction) {
+                Ok(missing) => {
+                    if !missing.is_empty() {
+                        unresolved.push(*hash);
+                    }
+                }
+                Err(err) => error!(target: "txs_pool", "resolve {} failed: {}", hash, err),
+            }
+        }
+        let mut removed = self
+            .descendants(&unresolved)
+            .iter()
+            .filter_map(|hash| self.remove(hash))
+            .collect::<Vec<_>>();
+        removed.sort_by_key(|entry| entry.sequence);
+        for entry in removed {
+            let hash = entry.transaction.hash();
+            if let Err(err) = self.add_transaction(entry.transaction) {
+                debug!(target: "txs_pool", "Drop unresolved transaction {}: {:?}", hash, err);
+            }
+        }
+    }
+
+    fn insert(&mut self, transaction: IndexedTransaction, fee: Capacity) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let size = codec::encode(transaction.transaction()).len();
+        self.total_bytes += size;
+        for input in &transaction.inputs {
+            self.spent.insert(input.previous_output, transaction.hash());
+        }
+        for dep in &transaction.deps {
+            self.readers.entry(*dep).or_default().push(transaction.hash());
+        }
+        self.entries.insert(
+            transaction.hash(),
+            PoolEntry {
+                transaction,
+                stage: Stage::Pending,
+                fee,
+                size,
+                sequence,
+            },
+        );
+    }
+
+    fn remove(&mut self, hash: &H256) -> Option<PoolEntry> {
+        let entry = self.entries.remove(hash)?;
+        self.total_bytes -= entry.size;
+        for input in &entry.transaction.inputs {
+            if self.spent.get(&input.previous_output) == Some(hash) {
+                self.spent.remove(&input.previous_output);
+            }
+        }
+        for dep in &entry.transaction.deps {
+            let is_empty = match self.readers.get_mut(dep) {
+                Some(readers) => {
+                    readers.retain(|reader| reader != hash);
+                    readers.is_empty()
+                }
+                None => false,
+            };
+            if is_empty {
+                self.readers.remove(dep);
+            }
+        }
+        Some(entry)
+    }
+
+    /// The transactions in the pool spending the same outputs as `transaction`, fails with
+    /// `PoolError::DoubleSpent` if one is already spent in the main chain.
+    fn conflicts(&self, transaction: &IndexedTransaction) -> Result<Vec<H256>, PoolError> {
+        let mut conflicts = Vec::new();
+        for input in &transaction.inputs {
+            let out_point = &input.previous_output;
+            if let Some(spender) = self.store.get_cell_spender(out_point)? {
+                return Err(PoolError::DoubleSpent(spender));
+            }
+            if let Some(spender) = self.spender(out_point) {
+                if !conflicts.contains(&spender) {
+                    conflicts.push(spender);
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Adds again the orphans waiting for the outputs of the `parents`, given as hash and
+    /// number of outputs, then the orphans waiting for the added ones.
+    fn promote_orphans(&mut self, mut parents: Vec<(H256, usize)>) {
+        while let Some((hash, outputs)) = parents.pop() {
+            let out_points = (0..outputs).map(|index| OutPoint {
+                hash,
+                index: index as u32,
+            });
+            for orphan in self.orphans.remove_waiting_for(out_points) {
+                let parent = (orphan.hash(), orphan.outputs.len());
+                match self.add(orphan) {
+                    Ok(InsertionResult::Orphan) => {}
+                    Ok(_) => parents.push(parent),
+                    Err(err) => {
+                        debug!(target: "txs_pool", "Drop orphan {}: {:?}", parent.0, err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The outputs spent or read by `transaction` which are neither in the pool nor in the
+    /// chain.
+    fn missing_out_points(
+        &self,
+        transaction: &IndexedTransaction,
+    ) -> Result<Vec<OutPoint>, StoreError> {
+        let mut missing = Vec::new();
+        let out_points = transaction
+            .inputs
+            .iter()
+            .map(|input| input.previous_output)
+            .chain(transaction.deps.iter().cloned());
+        for out_point in out_points {
+            if !missing.contains(&out_point) && self.resolve(&out_point)?.is_none() {
+                missing.push(out_point);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The transactions to evict so that `transaction`, paying `fee`, fits in the limits once
+    /// the `replaced` ones are gone, fails with `PoolError::OverCapacity` if it has the lowest
+    /// fee rate.
+    ///
+    /// Only transactions without descendants are evicted, so nothing is left without inputs or
+    /// deps, and the parents of `transaction` are kept.
+    fn make_room(
+        &self,
+        transaction: &IndexedTransaction,
+        fee: Capacity,
+        replaced: &[H256],
+    ) -> Result<Vec<H256>, PoolError> {
+        let size = codec::encode(transaction.transaction()).len();
+        let mut removed = replaced.iter().cloned().collect::<FnvHashSet<_>>();
+        let kept = self.parents(transaction).collect::<FnvHashSet<_>>();
+        let mut count = self.entries.len() - replaced.len() + 1;
+        let mut bytes = self.total_bytes + size;
+        bytes -= replaced.iter().map(|hash| self.entries[hash].size).sum::<usize>();
+
+        let mut evicted = Vec::new();
+        while count > self.config.max_transactions || bytes > self.config.max_bytes {
+            let hash = match self.lowest_fee_rate_leaf(&removed, &kept) {
+                Some(hash) => hash,
+                None => return Err(PoolError::OverCapacity),
+            };
+            let entry = &self.entries[&hash];
+            if cmp_fee_rate((fee, size), (entry.fee, entry.size)) != Ordering::Greater {
+                return Err(PoolError::OverCapacity);
+            }
+            removed.insert(hash);
+            evicted.push(hash);
+            count -= 1;
+            bytes -= entry.size;
+        }
+        Ok(evicted)
+    }
+
+    /// Evicts the transactions with the lowest fee rate until the pool is within its limits.
+    fn limit(&mut self) {
+        let none = FnvHashSet::default();
+        while self.entries.len() > self.config.max_transactions
+            || self.total_bytes > self.config.max_bytes
+        {
+            let hash = match self.lowest_fee_rate_leaf(&none, &none) {
+                Some(hash) => hash,
+                None => break,
+            };
+            self.remove(&hash);
+            self.evicted.push(hash);
+        }
+    }
+
+    /// The entry with the lowest fee rate, the newest on ties, among the ones which are not
+    /// `removed` or `kept` and have no descendants left.
+    fn lowest_fee_rate_leaf(
+        &self,
+        removed: &FnvHashSet<H256>,
+        kept: &FnvHashSet<H256>,
+    ) -> Option<H256> {
+        self.entries
+            .iter()
+            .filter(|(hash, _)| !removed.contains(hash) && !kept.contains(hash))
+            .filter(|(hash, entry)| {
+                self.children(**hash, entry).all(|child| removed.contains(&child))
+            })
+            .min_by(|(_, a), (_, b)| {
+                cmp_fee_rate((a.fee, a.size), (b.fee, b.size))
+                    .then(b.sequence.cmp(&a.sequence))
+            })
+            .map(|(hash, _)| *hash)
+    }
+
+    /// Whether `transaction`, paying `fee`, pays a strictly higher fee than the `evicted`
+    /// transactions together, and a strictly higher fee rate than each of the `conflicts`.
+    fn pays_for_replacement(
+        &self,
+        transaction: &IndexedTransaction,
+        fee: Capacity,
+        conflicts: &[H256],
+        evicted: &[H256],
+    ) -> bool {
+        let size = codec::encode(transaction.transaction()).len();
+        let evicted_fee = evicted.iter().map(|hash| self.entries[hash].fee).sum::<Capacity>();
+        fee > evicted_fee
+            && conflicts.iter().all(|hash| {
+                let entry = &self.entries[hash];
+                cmp_fee_rate((fee, size), (entry.fee, entry.size)) == Ordering::Greater
+            })
+    }
+
+    /// `hashes` and the transactions in the pool spending or reading their outputs,
+    /// recursively.
+    fn descendants(&self, hashes: &[H256]) -> Vec<H256> {
+        let mut descendants = Vec::new();
+        let mut visited = FnvHashSet::default();
+        let mut queue = hashes.to_vec();
+        while let Some(hash) = queue.pop() {
+            let entry = match self.entries.get(&hash) {
+                Some(entry) if visited.insert(hash) => entry,
+                _ => continue,
+            };
+            descendants.push(hash);
+            queue.extend(self.children(hash, entry));
+        }
+        descendants
+    }
+
+    /// The transactions in the pool spending or reading the outputs of `entry`.
+    fn children<'a>(
+        &'a self,
+        hash: H256,
+        entry: &'a PoolEntry,
+    ) -> impl Iterator<Item = H256> + 'a {
+        (0..entry.transaction.outputs.len()).flat_map(move |index| {
+            let out_point = OutPoint {
+                hash,
+                index: index as u32,
+            };
+            let readers = self.readers.get(&out_point).into_iter().flatten().cloned();
+            self.spender(&out_point).into_iter().chain(readers)
+        })
+    }
+
+    /// Finds the output in the pool first, then in the main chain.
+    fn resolve(&self, out_point: &OutPoint) -> Result<Option<CellOutput>, StoreError> {
+        let index = out_point.index as usize;
+        match self.entries.get(&out_point.hash) {
+            Some(entry) => Ok(entry.transaction.outputs.get(index).cloned()),
+            None => Ok(self
+                .store
+                .get_transaction(&out_point.hash)?
+                .and_then(|tx| tx.outputs.get(index).cloned())),
+        }
+    }
+
+    fn fee(&self, transaction: &IndexedTransaction) -> Result<Capacity, StoreError> {
+        let mut inputs_capacity: Capacity = 0;
+        for input in &transaction.inputs {
+            if let Some(output) = self.resolve(&input.previous_output)? {
+                inputs_capacity = inputs_capacity.saturating_add(output.capacity);
+            }
+        }
+        Ok(inputs_capacity.saturating_sub(transaction.outputs_capacity()))
+    }
+
+    /// The transactions in the pool whose outputs are spent or read by `transaction`.
+    fn parents<'a>(
+        &'a self,
+        transaction: &'a IndexedTransaction,
+    ) -> impl Iterator<Item = H256> + 'a {
+        transaction
+            .inputs
+            .iter()
+            .map(|input| input.previous_output.hash)
+            .chain(transaction.deps.iter().map(|dep| dep.hash))
+            .filter(move |hash| self.entries.contains_key(hash))
+    }
+
+    /// Greedily takes the package with the highest fee rate among the `is_candidate` entries,
+    /// until `max` transactions are taken or no package fits anymore.
+    ///
+    /// A package is the candidate and its ancestors which are not selected yet. Ancestors must
+    /// be candidates too, unless they are `is_satisfied`, otherwise the candidate is skipped.
+    ///
+    /// Packages are scored once and kept in a max-heap. Selecting a package only changes the
+    /// packages of its descendants, which are scored again, and their previous scores are
+    /// dropped when popped.
+    fn select<C, D>(
+        &self,
+        max: usize,
+        max_bytes: Option<usize>,
+        is_candidate: C,
+        is_satisfied: D,
+    ) -> Vec<IndexedTransaction>
+    where
+        C: Fn(Stage) -> bool,
+        D: Fn(Stage) -> bool,
+    {
+        let mut selected = Vec::new();
+        let mut selected_set = FnvHashSet::default();
+        let mut skipped = FnvHashSet::default();
+        let mut heap = BinaryHeap::new();
+        let mut bytes = 0;
+        let mut unscored = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| is_candidate(entry.stage))
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+
+        loop {
+            for hash in unscored.drain(..) {
+                match self.package(&hash, &selected_set, &is_candidate, &is_satisfied) {
+                    Some(package) => heap.push(self.package_score(package)),
+                    None => {
+                        skipped.insert(hash);
+                    }
+                }
+            }
+            if selected.len() >= max {
+                break;
+            }
+
+            let PackageScore { package, size, .. } = match heap.pop() {
+                Some(score) => score,
+                None => break,
+            };
+            let root = *package.last().expect("package should contain the candidate");
+            // Outdated by the selection of an ancestor
+            if skipped.contains(&root) || package.iter().any(|hash| selected_set.contains(hash)) {
+                continue;
+            }
+            let fits_bytes = max_bytes.is_none_or(|max_bytes| bytes + size <= max_bytes);
+            if selected.len() + package.len() > max || !fits_bytes {
+                skipped.insert(root);
+                continue;
+            }
+            bytes += size;
+            for hash in &package {
+                selected_set.insert(*hash);
+                selected.push(self.entries[hash].transaction.clone());
+            }
+            unscored = self
+                .descendants(&package)
+                .into_iter()
+                .filter(|hash| {
+                    !selected_set.contains(hash)
+                        && !skipped.contains(hash)
+                        && is_candidate(self.entries[hash].stage)
+                })
+                .collect();
+        }
+        selected
+    }
+
+    fn package_score(&self, package: Vec<H256>) -> PackageScore {
+        let (fee, size) = package.iter().fold((0, 0), |(fee, size), hash| {
+            let entry = &self.entries[hash];
+            (fee + entry.fee, size + entry.size)
+        });
+        let root = package.last().expect("package should contain the candidate");
+        PackageScore {
+            sequence: self.entries[root].sequence,
+            package,
+            fee,
+            size,
+        }
+    }
+
+    /// The not yet selected ancestors of `hash` and `hash` itself, parents first.
+    fn package<C, D>(
+        &self,
+        hash: &H256,
+        selected: &FnvHashSet<H256>,
+        is_candidate: &C,
+        is_satisfied: &D,
+    ) -> Option<Vec<H256>>
+    where
+        C: Fn(Stage) -> bool,
+        D: Fn(Stage) -> bool,
+    {
+        let mut package = Vec::new();
+        let mut visited = FnvHashSet::default();
+        // Iterative post-order traversal, a transaction is pushed after all its parents
+        let mut stack = vec![(*hash, false)];
+        while let Some((hash, expanded)) = stack.pop() {
+            if expanded {
+                package.push(hash);
+                continue;
+            }
+            if selected.contains(&hash) || !visited.insert(hash) {
+                continue;
+            }
+            let entry = &self.entries[&hash];
+            if !is_candidate(entry.stage) {
+                if is_satisfied(entry.stage) {
+                    continue;
+                }
+                return None;
+            }
+            stack.push((hash, true));
+            stack.extend(self.parents(&entry.transaction).map(|parent| (parent, false)));
+        }
+        Some(package)
+    }
+}
+
+/// A package in `select`, ordered by fee rate then older candidate first.
+#[derive(PartialEq, Eq)]
+struct PackageScore {
+    /// Parents first, the candidate last
+    package: Vec<H256>,
+    fee: Capacity,
+    size: usize,
+    /// The sequence of the candidate
+    sequence: u64,
+}
+
+impl Ord for PackageScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_fee_rate((self.fee, self.size), (other.fee, other.size))
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for PackageScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares the fee rates of two `(fee, size)` pairs, without rounding.
+fn cmp_fee_rate(a: (Capacity, usize), b: (Capacity, usize)) -> Ordering {
+    (u128::from(a.0) * b.1 as u128).cmp(&(u128::from(b.0) * a.1 as u128))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{CellInput, Header, MemoryStore, StoreBatch, Transaction};
+
+    fn new_pool(
+        propagation_time: BlockNumber,
+        propagation_timeout: BlockNumber,
+        config: TransactionPoolConfig,
+    ) -> TransactionPool<MemoryStore> {
+        let consensus = Consensus {
+            transaction_propagation_time: propagation_time,
+            transaction_propagation_timeout: propagation_timeout,
+            ..Consensus::default()
+        };
+        let store = new_store(&[block(0, vec![funding()], vec![])]);
+        TransactionPool::new(Arc::new(store), 0, &consensus, config)
+    }
+
+    /// A pool on a chain with `funding` attached, to spend its outputs.
+    fn new_funded_pool(config: TransactionPoolConfig) -> TransactionPool<MemoryStore> {
+        new_chain_pool(&[block(0, vec![funding()], vec![])], config)
+    }
+
+    /// A pool on a chain with the blocks attached, to spend their outputs.
+    fn new_chain_pool(
+        blocks: &[IndexedBlock],
+        config: TransactionPoolConfig,
+    ) -> TransactionPool<MemoryStore> {
+        TransactionPool::new(Arc::new(new_store(blocks)), 0, &Consensus::default(), config)
+    }
+
+    fn new_store(blocks: &[IndexedBlock]) -> MemoryStore {
+        let store = MemoryStore::default();
+        let mut batch = StoreBatch::default();
+        for block in blocks {
+            batch.insert_block(block);
+            batch.attach_block(block);
+        }
+        store.write(batch).expect("write should be ok");
+        store
+    }
+
+    /// Spends `inputs` and creates one output of each capacity in `outputs`.
+    fn spend(inputs: Vec<OutPoint>, outputs: Vec<Capacity>) -> IndexedTransaction {
+        Transaction {
+            inputs: inputs
+                .into_iter()
+                .map(|out_point| CellInput::new(out_point, vec![]))
+                .collect(),
+            outputs: outputs
+                .into_iter()
+                .map(|capacity| CellOutput::new(capacity, vec![], vec![]))
+                .collect(),
+            ..Transaction::default()
+        }
+        .into()
+    }
+
+    /// Spends `input` and reads `dep`, creating one output of `capacity`.
+    fn read(input: OutPoint, dep: OutPoint, capacity: Capacity) -> IndexedTransaction {
+        Transaction {
+            deps: vec![dep],
+            ..spend(vec![input], vec![capacity]).transaction().clone()
+        }
+        .into()
+    }
+
+    /// The outputs spent by `transaction`, on the chain of `new_pool` and `new_funded_pool`.
+    fn funding() -> IndexedTransaction {
+        spend(vec![], vec![1000; 8])
+    }
+
+    fn transaction(index: u32) -> IndexedTransaction {
+        spend(vec![out_point(&funding(), index)], vec![100])
+    }
+
+    fn out_point(transaction: &IndexedTransaction, index: u32) -> OutPoint {
+        OutPoint {
+            hash: transaction.hash(),
+            index,
+        }
+    }
+
+    fn block(
+        number: BlockNumber,
+        commits: Vec<IndexedTransaction>,
+        proposals: Vec<IndexedTransaction>,
+    ) -> IndexedBlock {
+        let header = Header {
+            number,
+            ..Header::default()
+        };
+        IndexedBlock::new(header, vec![], commits, proposals)
+    }
+
+    #[test]
+    fn test_stages() {
+        let mut pool = new_pool(2, 3, TransactionPoolConfig::default());
+        let (tx1, tx2) = (transaction(1), transaction(2));
+        assert_eq!(pool.add_transaction(tx1.clone()), Ok(InsertionResult::Pending));
+        assert_eq!(pool.add_transaction(tx2.clone()), Ok(InsertionResult::Pending));
+        assert_eq!(pool.add_transaction(tx1.clone()), Err(PoolError::AlreadyInPool));
+        assert_eq!(pool.get_proposal_commit_txs(10, 10), (vec![tx1.clone(), tx2.clone()], vec![]));
+        assert_eq!(pool.get_proposal_commit_txs(1, 10).0, vec![tx1.clone()]);
+
+        pool.reconcile_block(&block(1, vec![], vec![tx1.clone()]));
+        assert_eq!(pool.stage(&tx1.hash()), Some(Stage::Proposed(1)));
+        assert_eq!(pool.get_proposal_commit_txs(10, 10), (vec![tx2.clone()], vec![]));
+
+        // Can be committed in block 3
+        pool.reconcile_block(&block(2, vec![], vec![tx2.clone()]));
+        assert_eq!(pool.get_proposal_commit_txs(10, 10), (vec![], vec![tx1.clone()]));
+
+        pool.reconcile_block(&block(3, vec![tx1.clone()], vec![]));
+        assert!(!pool.contains(&tx1.hash()));
+        assert_eq!(pool.get_proposal_commit_txs(10, 10), (vec![], vec![tx2.clone()]));
+
+        // Not committed before the timeout, proposed again
+        pool.reconcile_block(&block(6, vec![], vec![]));
+        assert_eq!(pool.stage(&tx2.hash()), Some(Stage::Pending));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_switch_fork() {
+        let mut pool = new_pool(1, 10, TransactionPoolConfig::default());
+        let (tx1, tx2, tx3) = (transaction(1), transaction(2), transaction(3));
+        pool.add_transaction(tx2.clone()).expect("add should be ok");
+        pool.reconcile_block(&block(1, vec![], vec![tx1.clone(), tx2.clone()]));
+        pool.reconcile_block(&block(2, vec![tx1.clone()], vec![]));
+        assert!(!pool.contains(&tx1.hash()));
+
+        let fork = ForkBlocks {
+            ancestor: Header::default(),
+            detached_blocks: vec![
+                block(1, vec![], vec![tx1.clone(), tx2.clone()]),
+                block(2, vec![tx1.clone()], vec![]),
+            ],
+            attached_blocks: vec![block(1, vec![], vec![tx3.clone()])],
+        };
+        pool.switch_fork(&fork);
+        assert_eq!(pool.stage(&tx1.hash()), Some(Stage::Pending));
+        assert_eq!(pool.stage(&tx2.hash()), Some(Stage::Pending));
+        assert_eq!(pool.stage(&tx3.hash()), Some(Stage::Proposed(1)));
+        assert_eq!(pool.get_proposal_commit_txs(10, 10).1, vec![tx3]);
+    }
+
+    #[test]
+    fn test_reconcile_admission() {
+        let config = TransactionPoolConfig {
+            max_transactions: 2,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_pool(1, 10, config);
+        let invalid = spend(vec![out_point(&funding(), 1)], vec![]);
+        let parent = spend(vec![out_point(&funding(), 2)], vec![100]);
+        let child = spend(vec![out_point(&parent, 0)], vec![50]);
+        let low = spend(vec![out_point(&funding(), 3)], vec![999]);
+
+        // Unknown proposals are verified, and kept as orphans when their inputs are unknown
+        pool.reconcile_block(&block(1, vec![], vec![invalid.clone(), child.clone()]));
+        assert!(!pool.contains(&invalid.hash()));
+        assert!(!pool.contains_orphan(&invalid.hash()));
+        assert!(pool.contains_orphan(&child.hash()));
+
+        pool.reconcile_block(&block(2, vec![], vec![parent.clone()]));
+        assert_eq!(pool.stage(&parent.hash()), Some(Stage::Proposed(2)));
+        assert_eq!(pool.stage(&child.hash()), Some(Stage::Pending));
+
+        // The pool is full of higher fee rates
+        pool.reconcile_block(&block(3, vec![], vec![low.clone()]));
+        assert!(!pool.contains(&low.hash()));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_switch_fork_admission() {
+        let mut pool = new_pool(1, 10, TransactionPoolConfig::default());
+        let parent = spend(vec![out_point(&funding(), 1)], vec![500]);
+        let child = spend(vec![out_point(&parent, 0)], vec![400]);
+        let lost_parent = spend(vec![out_point(&funding(), 2)], vec![500]);
+        let lost_child = spend(vec![out_point(&lost_parent, 0)], vec![400]);
+        let rival = spend(vec![out_point(&funding(), 2)], vec![600]);
+        let attached = block(1, vec![rival.clone()], vec![]);
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&attached);
+        batch.attach_block(&attached);
+        pool.store.write(batch).expect("write should be ok");
+
+        let fork = ForkBlocks {
+            ancestor: Header::default(),
+            detached_blocks: vec![
+                block(1, vec![parent.clone(), lost_parent.clone()], vec![]),
+                block(2, vec![child.clone(), lost_child.clone()], vec![]),
+            ],
+            attached_blocks: vec![attached],
+        };
+        pool.switch_fork(&fork);
+        // Parents are added before their children
+        assert_eq!(pool.stage(&parent.hash()), Some(Stage::Pending));
+        assert_eq!(pool.stage(&child.hash()), Some(Stage::Pending));
+        // The rival is committed in the new chain, the child of the lost parent waits for it
+        assert!(!pool.contains(&lost_parent.hash()));
+        assert!(!pool.contains_orphan(&lost_parent.hash()));
+        assert!(pool.contains_orphan(&lost_child.hash()));
+    }
+
+    #[test]
+    fn test_switch_fork_unresolved() {
+        let mut pool = new_pool(1, 10, TransactionPoolConfig::default());
+        let lost_parent = spend(vec![out_point(&funding(), 2)], vec![500, 500]);
+        let rival = spend(vec![out_point(&funding(), 2)], vec![600]);
+        let detached = block(1, vec![lost_parent.clone()], vec![]);
+        let attached = block(1, vec![rival.clone()], vec![]);
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&detached);
+        batch.attach_block(&detached);
+        pool.store.write(batch).expect("write should be ok");
+
+        let child = spend(vec![out_point(&lost_parent, 0)], vec![400]);
+        let grandchild = spend(vec![out_point(&child, 0)], vec![300]);
+        let reader = read(out_point(&funding(), 3), out_point(&lost_parent, 1), 900);
+        for tx in &[&child, &grandchild, &reader] {
+            pool.add_transaction((*tx).clone()).expect("add should be ok");
+        }
+        pool.reconcile_block(&block(2, vec![], vec![child.clone()]));
+        assert_eq!(pool.stage(&child.hash()), Some(Stage::Proposed(2)));
+
+        let mut batch = StoreBatch::default();
+        batch.detach_block(&detached);
+        batch.insert_block(&attached);
+        batch.attach_block(&attached);
+        pool.store.write(batch).expect("write should be ok");
+        let fork = ForkBlocks {
+            ancestor: Header::default(),
+            detached_blocks: vec![detached],
+            attached_blocks: vec![attached],
+        };
+        pool.switch_fork(&fork);
+        // The lost parent conflicts with the rival, its descendants wait for it again
+        assert!(!pool.contains(&lost_parent.hash()));
+        for tx in &[&child, &grandchild, &reader] {
+            assert!(!pool.contains(&tx.hash()));
+            assert!(pool.contains_orphan(&tx.hash()));
+        }
+        assert!(pool.is_empty());
+        assert_eq!(pool.get_proposal_commit_txs(10, 10), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_fee_rate_selection() {
+        let funding = funding();
+        let mut pool = new_funded_pool(TransactionPoolConfig::default());
+
+        let low = spend(vec![out_point(&funding, 0)], vec![990]);
+        let medium = spend(vec![out_point(&funding, 1)], vec![950]);
+        let parent = spend(vec![out_point(&funding, 2)], vec![999]);
+        let child = spend(vec![out_point(&parent, 0)], vec![800]);
+        for tx in &[&low, &medium, &parent, &child] {
+            pool.add_transaction((*tx).clone()).expect("add should be ok");
+        }
+
+        // The child pays for its parent, and comes after it
+        let proposals = pool.get_proposal_commit_txs(4, 0).0;
+        assert_eq!(proposals, vec![parent.clone(), child.clone(), medium.clone(), low.clone()]);
+        // The package does not fit in 3 after medium
+        let proposals = pool.get_proposal_commit_txs(3, 0).0;
+        assert_eq!(proposals, vec![parent.clone(), child.clone(), medium.clone()]);
+        assert_eq!(pool.get_proposal_commit_txs(1, 0).0, vec![medium.clone()]);
+
+        // Parents already proposed are not proposed again
+        pool.reconcile_block(&block(1, vec![], vec![parent.clone()]));
+        assert_eq!(pool.get_proposal_commit_txs(1, 0).0, vec![child.clone()]);
+
+        // The child cannot be committed before its parent is proposed
+        assert_eq!(pool.get_proposal_commit_txs(0, 4).1, vec![parent.clone()]);
+    }
+
+    #[test]
+    fn test_dep_selection() {
+        let funding = funding();
+        let mut pool = new_funded_pool(TransactionPoolConfig::default());
+        let parent = spend(vec![out_point(&funding, 0)], vec![900]);
+        let reader = read(out_point(&funding, 1), out_point(&parent, 0), 990);
+        pool.add_transaction(parent.clone()).expect("add should be ok");
+        pool.add_transaction(reader.clone()).expect("add should be ok");
+
+        // The reader is scored again once its parent is selected alone
+        assert_eq!(pool.get_proposal_commit_txs(10, 0).0, vec![parent.clone(), reader.clone()]);
+        assert_eq!(pool.get_proposal_commit_txs(1, 0).0, vec![parent]);
+    }
+
+    #[test]
+    fn test_max_commit_bytes() {
+        let (tx1, tx2) = (transaction(1), transaction(2));
+        let size = codec::encode(tx1.transaction()).len();
+        let config = TransactionPoolConfig {
+            max_commit_bytes: Some(size * 3 / 2),
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_pool(0, 10, config);
+        pool.reconcile_block(&block(1, vec![], vec![tx1.clone(), tx2.clone()]));
+        assert_eq!(pool.get_proposal_commit_txs(0, 10).1, vec![tx1]);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let funding = funding();
+        let spent = spend(vec![out_point(&funding, 3)], vec![1000]);
+        let blocks = [block(0, vec![funding.clone(), spent.clone()], vec![])];
+        let mut pool = new_chain_pool(&blocks, TransactionPoolConfig::default());
+
+        let tx1 = spend(vec![out_point(&funding, 0)], vec![900]);
+        let tx2 = spend(vec![out_point(&funding, 0), out_point(&funding, 1)], vec![1800]);
+        pool.add_transaction(tx1.clone()).expect("add should be ok");
+        assert_eq!(pool.add_transaction(tx2), Err(PoolError::DoubleSpent(tx1.hash())));
+        assert_eq!(pool.add_transaction(funding.clone()), Err(PoolError::DuplicateOutput));
+        let tx3 = spend(vec![out_point(&funding, 3)], vec![900]);
+        assert_eq!(pool.add_transaction(tx3), Err(PoolError::DoubleSpent(spent.hash())));
+
+        // Conflicts with a committed transaction are dropped
+        let tx4 = spend(vec![out_point(&funding, 1)], vec![900]);
+        let committed = spend(vec![out_point(&funding, 1)], vec![800]);
+        pool.add_transaction(tx4.clone()).expect("add should be ok");
+        pool.reconcile_block(&block(1, vec![committed], vec![]));
+        assert!(!pool.contains(&tx4.hash()));
+        assert_eq!(pool.spender(&out_point(&funding, 1)), None);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_replace_conflicts() {
+        let funding = funding();
+        let config = TransactionPoolConfig {
+            conflict_policy: ConflictPolicy::Replace,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+
+        let parent = spend(vec![out_point(&funding, 0)], vec![900]);
+        let child = spend(vec![out_point(&parent, 0)], vec![800]);
+        let other = spend(vec![out_point(&funding, 1)], vec![900]);
+        for tx in &[&parent, &child, &other] {
+            pool.add_transaction((*tx).clone()).expect("add should be ok");
+        }
+
+        // Cannot spend the outputs of the transactions it evicts
+        let invalid = spend(vec![out_point(&funding, 0), out_point(&child, 0)], vec![1700]);
+        assert_eq!(pool.add_transaction(invalid), Err(PoolError::DoubleSpent(parent.hash())));
+
+        let replacement = spend(vec![out_point(&funding, 0)], vec![950]);
+        assert_eq!(
+            pool.add_transaction(replacement.clone()),
+            Ok(InsertionResult::Replaced(vec![parent.hash(), child.hash()]))
+        );
+        assert!(!pool.contains(&parent.hash()));
+        assert!(!pool.contains(&child.hash()));
+        assert_eq!(pool.spender(&out_point(&parent, 0)), None);
+        assert_eq!(pool.spender(&out_point(&funding, 0)), Some(replacement.hash()));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let funding = funding();
+        let config = TransactionPoolConfig {
+            conflict_policy: ConflictPolicy::ReplaceByFee,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+
+        let parent = spend(vec![out_point(&funding, 0)], vec![900]);
+        let child = spend(vec![out_point(&parent, 0)], vec![800]);
+        let other = spend(vec![out_point(&funding, 1)], vec![900]);
+        for tx in &[&parent, &child, &other] {
+            pool.add_transaction((*tx).clone()).expect("add should be ok");
+        }
+
+        // Pays more than the parent, but not more than the parent and the child
+        let cheap = spend(vec![out_point(&funding, 0)], vec![850]);
+        assert_eq!(pool.add_transaction(cheap), Err(PoolError::DoubleSpent(parent.hash())));
+        let bump = spend(vec![out_point(&funding, 0)], vec![750]);
+        assert_eq!(
+            pool.add_transaction(bump.clone()),
+            Ok(InsertionResult::Replaced(vec![parent.hash(), child.hash()]))
+        );
+
+        // Pays more, but at a lower fee rate
+        let large: IndexedTransaction = Transaction {
+            inputs: vec![CellInput::new(out_point(&funding, 1), vec![])],
+            outputs: vec![CellOutput::new(890, vec![0; 1000], vec![])],
+            ..Transaction::default()
+        }
+        .into();
+        assert_eq!(pool.add_transaction(large), Err(PoolError::DoubleSpent(other.hash())));
+        let bump_other = spend(vec![out_point(&funding, 1)], vec![850]);
+        assert_eq!(
+            pool.add_transaction(bump_other),
+            Ok(InsertionResult::Replaced(vec![other.hash()]))
+        );
+        assert!(pool.contains(&bump.hash()));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_replace_by_fee_dep() {
+        let funding = funding();
+        let config = TransactionPoolConfig {
+            conflict_policy: ConflictPolicy::ReplaceByFee,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+        let parent = spend(vec![out_point(&funding, 0)], vec![900]);
+        let reader = read(out_point(&funding, 1), out_point(&parent, 0), 950);
+        pool.add_transaction(parent.clone()).expect("add should be ok");
+        pool.add_transaction(reader.clone()).expect("add should be ok");
+
+        // Pays more than the parent, but not more than the parent and the reader
+        let cheap = spend(vec![out_point(&funding, 0)], vec![860]);
+        assert_eq!(pool.add_transaction(cheap), Err(PoolError::DoubleSpent(parent.hash())));
+        let bump = spend(vec![out_point(&funding, 0)], vec![800]);
+        assert_eq!(
+            pool.add_transaction(bump),
+            Ok(InsertionResult::Replaced(vec![parent.hash(), reader.hash()]))
+        );
+        assert!(!pool.contains(&reader.hash()));
+        assert!(pool.readers.is_empty());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let funding = funding();
+        let config = TransactionPoolConfig {
+            max_transactions: 2,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+
+        let low = spend(vec![out_point(&funding, 0)], vec![990]);
+        let high = spend(vec![out_point(&funding, 1)], vec![900]);
+        let medium = spend(vec![out_point(&funding, 2)], vec![950]);
+        pool.add_transaction(low.clone()).expect("add should be ok");
+        pool.add_transaction(high.clone()).expect("add should be ok");
+        assert_eq!(pool.add_transaction(medium.clone()), Ok(InsertionResult::Pending));
+        assert!(!pool.contains(&low.hash()));
+        assert_eq!(pool.take_evicted(), vec![low.hash()]);
+        assert_eq!(pool.take_evicted(), vec![]);
+
+        let lowest = spend(vec![out_point(&funding, 3)], vec![995]);
+        assert_eq!(pool.add_transaction(lowest), Err(PoolError::OverCapacity));
+
+        // The parent of the new transaction is kept
+        let child = spend(vec![out_point(&high, 0)], vec![700]);
+        pool.add_transaction(child.clone()).expect("add should be ok");
+        assert!(pool.contains(&high.hash()));
+        assert_eq!(pool.take_evicted(), vec![medium.hash()]);
+
+        // Proposals from blocks are admitted, then the pool is trimmed without leaving the
+        // child of an evicted transaction
+        let proposed = spend(vec![out_point(&funding, 4)], vec![0]);
+        pool.reconcile_block(&block(1, vec![], vec![proposed.clone()]));
+        assert_eq!(pool.take_evicted(), vec![child.hash()]);
+        assert!(pool.contains(&proposed.hash()));
+        assert!(pool.contains(&high.hash()));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_capacity_dep() {
+        let funding = funding();
+        let config = TransactionPoolConfig {
+            max_transactions: 2,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+        let parent = spend(vec![out_point(&funding, 0)], vec![990]);
+        let reader = read(out_point(&funding, 1), out_point(&parent, 0), 900);
+        pool.add_transaction(parent.clone()).expect("add should be ok");
+        pool.add_transaction(reader.clone()).expect("add should be ok");
+
+        // The parent is not evicted while the reader needs it
+        let medium = spend(vec![out_point(&funding, 2)], vec![950]);
+        assert_eq!(pool.add_transaction(medium), Err(PoolError::OverCapacity));
+        assert!(pool.contains(&parent.hash()));
+        assert!(pool.contains(&reader.hash()));
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let funding = funding();
+        let (tx1, tx2) = (
+            spend(vec![out_point(&funding, 0)], vec![900]),
+            spend(vec![out_point(&funding, 1)], vec![800]),
+        );
+        let size = codec::encode(tx1.transaction()).len();
+        let config = TransactionPoolConfig {
+            max_bytes: size * 3 / 2,
+            ..TransactionPoolConfig::default()
+        };
+        let mut pool = new_funded_pool(config);
+        pool.add_transaction(tx1.clone()).expect("add should be ok");
+        pool.add_transaction(tx2.clone()).expect("add should be ok");
+        assert_eq!(pool.take_evicted(), vec![tx1.hash()]);
+        assert_eq!(pool.total_bytes(), size);
+    }
+
+    #[test]
+    fn test_orphans() {
+        let mut pool = new_pool(1, 10, TransactionPoolConfig::default());
+        let parent = spend(vec![out_point(&funding(), 0)], vec![900, 900]);
+        let child = spend(vec![out_point(&parent, 0)], vec![800]);
+        let grandchild = spend(vec![out_point(&child, 0), out_point(&parent, 1)], vec![1600]);
+        assert_eq!(pool.add_transaction(grandchild.clone()), Ok(InsertionResult::Orphan));
+        assert_eq!(pool.add_transaction(child.clone()), Ok(InsertionResult::Orphan));
+        assert_eq!(pool.add_transaction(child.clone()), Err(PoolError::AlreadyInPool));
+        assert!(pool.contains_orphan(&child.hash()));
+        assert!(pool.is_empty());
+
+        // The parent brings the orphans in, every transaction after its parents
+        assert_eq!(pool.add_transaction(parent.clone()), Ok(InsertionResult::Pending));
+        assert!(!pool.contains_orphan(&child.hash()));
+        assert!(!pool.contains_orphan(&grandchild.hash()));
+        assert_eq!(
+            pool.get_proposal_commit_txs(10, 0).0,
+            vec![parent.clone(), child.clone(), grandchild.clone()]
+        );
+
+        // Orphans are promoted when their parent is committed, or dropped if they conflict
+        let committed = spend(vec![out_point(&funding(), 1)], vec![900]);
+        let orphan = spend(vec![out_point(&committed, 0)], vec![800]);
+        let conflict = spend(vec![out_point(&committed, 0), out_point(&funding(), 1)], vec![1800]);
+        assert_eq!(pool.add_transaction(orphan.clone()), Ok(InsertionResult::Orphan));
+        assert_eq!(pool.add_transaction(conflict.clone()), Ok(InsertionResult::Orphan));
+        let tip = block(1, vec![committed], vec![]);
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&tip);
+        batch.attach_block(&tip);
+        pool.store.write(batch).expect("write should be ok");
+        pool.reconcile_block(&tip);
+        assert_eq!(pool.stage(&orphan.hash()), Some(Stage::Pending));
+        assert!(!pool.contains(&conflict.hash()));
+        assert!(!pool.contains_orphan(&conflict.hash()));
+    }
+}
@@ -0,0 +1,335 @@
+use std::ops::Deref;
+
+use bincode;
+use serde::Serialize;
+
+use super::{blake2b_256, BlockNumber, Capacity, H256};
+
+#[derive(Clone, Eq, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub parent_hash: H256,
+    pub number: BlockNumber,
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub difficulty: u64,
+    /// Commits to the commit transactions, see `transactions_root`
+    pub transactions_root: H256,
+    /// Commits to the proposal transactions, see `transactions_root`
+    pub proposals_root: H256,
+    /// Commits to the uncles, see `uncles_hash`
+    pub uncles_hash: H256,
+    pub uncles_count: u32,
+    pub nonce: u64,
+    /// Cuckoo cycle edge indices
+    pub proof: Vec<u32>,
+}
+
+impl Header {
+    pub fn hash(&self) -> H256 {
+        hash_of(self)
+    }
+
+    /// The header fields covered by the proof of work, with `nonce` instead of `self.nonce`.
+    pub fn pow_message(&self, nonce: u64) -> Vec<u8> {
+        let mut message = Vec::with_capacity(168);
+        message.extend_from_slice(self.parent_hash.as_bytes());
+        message.extend_from_slice(self.transactions_root.as_bytes());
+        message.extend_from_slice(self.proposals_root.as_bytes());
+        message.extend_from_slice(self.uncles_hash.as_bytes());
+        let uncles_count = u64::from(self.uncles_count);
+        for field in &[self.number, self.timestamp, self.difficulty, uncles_count, nonce] {
+            for shift in 0..8 {
+                message.push((field >> (8 * shift)) as u8);
+            }
+        }
+        message
+    }
+}
+
+/// Hashes the serialization of the value without the codec version byte, so hashes do not
+/// change when only the storage encoding does.
+fn hash_of<T: Serialize>(value: &T) -> H256 {
+    blake2b_256(&bincode::serialize(value).expect("serialize into memory should be ok"))
+}
+
+/// Commits to a list of transactions: the hash of their concatenated hashes, or zero for an
+/// empty list so a default header matches an empty body.
+pub fn transactions_root(transactions: &[IndexedTransaction]) -> H256 {
+    hashes_root(transactions.iter().map(|transaction| transaction.hash()))
+}
+
+/// Commits to the uncles the same way as `transactions_root`.
+pub fn uncles_hash(uncles: &[Header]) -> H256 {
+    hashes_root(uncles.iter().map(|uncle| uncle.hash()))
+}
+
+fn hashes_root<I: ExactSizeIterator<Item = H256>>(hashes: I) -> H256 {
+    if hashes.len() == 0 {
+        return H256::default();
+    }
+    let mut bytes = Vec::with_capacity(32 * hashes.len());
+    for hash in hashes {
+        bytes.extend_from_slice(hash.as_bytes());
+    }
+    blake2b_256(&bytes)
+}
+
+/// A block with its hash and the commitments to its body computed once on construction, so
+/// the fields are read only.
+///
+/// The computed commitments may differ from the ones claimed in the header, the block
+/// verifier rejects such blocks.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "RawBlock", into = "RawBlock")]
+pub struct IndexedBlock {
+    header: Header,
+    uncles: Vec<Header>,
+    commit_transactions: Vec<IndexedTransaction>,
+    proposal_transactions: Vec<IndexedTransaction>,
+    hash: H256,
+    transactions_root: H256,
+    proposals_root: H256,
+    uncles_hash: H256,
+}
+
+/// The encoded fields of `IndexedBlock`, the hashes are computed again on decoding.
+#[derive(Clone, Serialize, Deserialize)]
+struct RawBlock {
+    header: Header,
+    uncles: Vec<Header>,
+    commit_transactions: Vec<IndexedTransaction>,
+    proposal_transactions: Vec<IndexedTransaction>,
+}
+
+impl From<RawBlock> for IndexedBlock {
+    fn from(block: RawBlock) -> Self {
+        IndexedBlock::new(
+            block.header,
+            block.uncles,
+            block.commit_transactions,
+            block.proposal_transactions,
+        )
+    }
+}
+
+impl From<IndexedBlock> for RawBlock {
+    fn from(block: IndexedBlock) -> Self {
+        RawBlock {
+            header: block.header,
+            uncles: block.uncles,
+            commit_transactions: block.commit_transactions,
+            proposal_transactions: block.proposal_transactions,
+        }
+    }
+}
+
+impl Default for IndexedBlock {
+    fn default() -> Self {
+        IndexedBlock::new(Header::default(), vec![], vec![], vec![])
+    }
+}
+
+impl IndexedBlock {
+    pub fn new(
+        header: Header,
+        uncles: Vec<Header>,
+        commit_transactions: Vec<IndexedTransaction>,
+        proposal_transactions: Vec<IndexedTransaction>,
+    ) -> Self {
+        let hash = header.hash();
+        let proposals_root = transactions_root(&proposal_transactions);
+        let transactions_root = transactions_root(&commit_transactions);
+        let uncles_hash = uncles_hash(&uncles);
+        IndexedBlock {
+            header,
+            uncles,
+            commit_transactions,
+            proposal_transactions,
+            hash,
+            transactions_root,
+            proposals_root,
+            uncles_hash,
+        }
+    }
+
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    /// The root of the commit transactions, to compare with `Header::transactions_root`
+    pub fn transactions_root(&self) -> H256 {
+        self.transactions_root
+    }
+
+    /// The root of the proposal transactions, to compare with `Header::proposals_root`
+    pub fn proposals_root(&self) -> H256 {
+        self.proposals_root
+    }
+
+    /// The hash of the uncles, to compare with `Header::uncles_hash`
+    pub fn uncles_hash(&self) -> H256 {
+        self.uncles_hash
+    }
+
+    pub fn number(&self) -> BlockNumber {
+        self.header.number
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn uncles(&self) -> &[Header] {
+        &self.uncles
+    }
+
+    /// Transactions committed in this block, starting with the cellbase
+    pub fn commit_transactions(&self) -> &[IndexedTransaction] {
+        &self.commit_transactions
+    }
+
+    /// Transactions proposed in this block, which may be committed in later blocks
+    pub fn proposal_transactions(&self) -> &[IndexedTransaction] {
+        &self.proposal_transactions
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub hash: H256,
+    pub index: u32,
+}
+
+impl OutPoint {
+    /// The outpoint spent by cellbase.
+    pub fn null() -> Self {
+        OutPoint {
+            hash: H256::default(),
+            index: u32::MAX,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CellInput {
+    pub previous_output: OutPoint,
+    /// Arguments which unlock the previous output
+    pub unlock: Vec<u8>,
+}
+
+impl CellInput {
+    pub fn new(previous_output: OutPoint, unlock: Vec<u8>) -> Self {
+        CellInput {
+            previous_output,
+            unlock,
+        }
+    }
+
+    pub fn new_cellbase() -> Self {
+        CellInput::new(OutPoint::null(), vec![])
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct CellOutput {
+    pub capacity: Capacity,
+    pub data: Vec<u8>,
+    /// The lock which must be unlocked to spend this output
+    pub lock: Vec<u8>,
+}
+
+impl CellOutput {
+    pub fn new(capacity: Capacity, data: Vec<u8>, lock: Vec<u8>) -> Self {
+        CellOutput {
+            capacity,
+            data,
+            lock,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct Transaction {
+    pub version: u32,
+    /// Cells read but not spent by the transaction
+    pub deps: Vec<OutPoint>,
+    pub inputs: Vec<CellInput>,
+    pub outputs: Vec<CellOutput>,
+}
+
+impl Transaction {
+    /// A cellbase paying `reward` in one output, `unlock` tells apart the cellbases of
+    /// different blocks.
+    pub fn new_cellbase(reward: Capacity, unlock: Vec<u8>) -> Self {
+        Transaction {
+            inputs: vec![CellInput::new(OutPoint::null(), unlock)],
+            outputs: vec![CellOutput::new(reward, vec![], vec![])],
+            ..Transaction::default()
+        }
+    }
+
+    pub fn hash(&self) -> H256 {
+        hash_of(self)
+    }
+
+    pub fn is_cellbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].previous_output.is_null()
+    }
+
+    pub fn outputs_capacity(&self) -> Capacity {
+        self.outputs.iter().map(|output| output.capacity).sum()
+    }
+}
+
+/// A transaction with its hash computed once on construction, it derefs to the transaction.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "Transaction", into = "Transaction")]
+pub struct IndexedTransaction {
+    transaction: Transaction,
+    hash: H256,
+}
+
+impl Default for IndexedTransaction {
+    fn default() -> Self {
+        Transaction::default().into()
+    }
+}
+
+impl IndexedTransaction {
+    pub fn new(transaction: Transaction) -> Self {
+        let hash = transaction.hash();
+        IndexedTransaction { transaction, hash }
+    }
+
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+}
+
+impl Deref for IndexedTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.transaction
+    }
+}
+
+impl From<Transaction> for IndexedTransaction {
+    fn from(transaction: Transaction) -> Self {
+        IndexedTransaction::new(transaction)
+    }
+}
+
+impl From<IndexedTransaction> for Transaction {
+    fn from(transaction: IndexedTransaction) -> Self {
+        transaction.transaction
+    }
+}
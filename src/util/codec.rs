@@ -0,0 +1,197 @@
+//! The canonical binary encoding of chain data, used for persistence and on the wire.
+//!
+//! An encoded value is a version byte followed by the bincode serialization of the value:
+//! fixed width little endian integers, and sequences prefixed by their length as a `u64`.
+//! Decoding is strict, so every value has exactly one encoding.
+
+use std::fmt;
+
+use bincode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Bumped on every incompatible change of the encoding.
+pub const CODEC_VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The input has no version byte
+    Empty,
+    /// The input was encoded by an incompatible version
+    Version(u8),
+    /// The payload is not an encoding of the expected type
+    Malformed(String),
+    /// The input continues after the value
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecError::Empty => write!(f, "empty input"),
+            CodecError::Version(version) => write!(
+                f,
+                "codec version mismatch: expected {}, actual {}",
+                CODEC_VERSION, version
+            ),
+            CodecError::Malformed(ref err) => write!(f, "malformed input: {}", err),
+            CodecError::TrailingBytes(len) => write!(f, "{} trailing bytes", len),
+        }
+    }
+}
+
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![CODEC_VERSION];
+    bincode::serialize_into(&mut bytes, value).expect("serialize into memory should be ok");
+    bytes
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+    let (&version, mut payload) = bytes.split_first().ok_or(CodecError::Empty)?;
+    if version != CODEC_VERSION {
+        return Err(CodecError::Version(version));
+    }
+    let value = bincode::deserialize_from(&mut payload)
+        .map_err(|err| CodecError::Malformed(err.to_string()))?;
+    if !payload.is_empty() {
+        return Err(CodecError::TrailingBytes(payload.len()));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{CellInput, CellOutput, Header, IndexedBlock, IndexedTransaction, OutPoint};
+    use util::{blake2b_256, Transaction, H256};
+
+    /// xorshift64, enough to generate arbitrary values without extra dependencies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+
+        fn bytes(&mut self, max_len: u64) -> Vec<u8> {
+            let len = self.below(max_len + 1);
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    fn header(rng: &mut Rng) -> Header {
+        Header {
+            parent_hash: H256::default(),
+            number: rng.next(),
+            timestamp: rng.next(),
+            difficulty: rng.next(),
+            transactions_root: H256::default(),
+            proposals_root: H256::default(),
+            uncles_hash: H256::default(),
+            uncles_count: rng.next() as u32,
+            nonce: rng.next(),
+            proof: (0..rng.below(4)).map(|_| rng.next() as u32).collect(),
+        }
+    }
+
+    fn out_point(rng: &mut Rng) -> OutPoint {
+        OutPoint {
+            hash: H256::default(),
+            index: rng.next() as u32,
+        }
+    }
+
+    fn transaction(rng: &mut Rng) -> IndexedTransaction {
+        Transaction {
+            version: rng.next() as u32,
+            deps: (0..rng.below(3)).map(|_| out_point(rng)).collect(),
+            inputs: (0..rng.below(3))
+                .map(|_| CellInput::new(out_point(rng), rng.bytes(8)))
+                .collect(),
+            outputs: (0..rng.below(3))
+                .map(|_| CellOutput::new(rng.next(), rng.bytes(8), rng.bytes(8)))
+                .collect(),
+        }
+        .into()
+    }
+
+    fn block(rng: &mut Rng) -> IndexedBlock {
+        IndexedBlock::new(
+            header(rng),
+            (0..rng.below(3)).map(|_| header(rng)).collect(),
+            (0..rng.below(3)).map(|_| transaction(rng)).collect(),
+            (0..rng.below(3)).map(|_| transaction(rng)).collect(),
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..100 {
+            let block = block(&mut rng);
+            assert_eq!(decode::<IndexedBlock>(&encode(&block)), Ok(block.clone()));
+            assert_eq!(decode::<Header>(&encode(block.header())).as_ref(), Ok(block.header()));
+            for tx in block.commit_transactions() {
+                assert_eq!(decode::<IndexedTransaction>(&encode(tx)).as_ref(), Ok(tx));
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let bytes = encode(&block(&mut rng));
+
+        assert_eq!(decode::<IndexedBlock>(&[]), Err(CodecError::Empty));
+        let mut versioned = bytes.clone();
+        versioned[0] = CODEC_VERSION + 1;
+        assert_eq!(
+            decode::<IndexedBlock>(&versioned),
+            Err(CodecError::Version(CODEC_VERSION + 1))
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode::<IndexedBlock>(&trailing), Err(CodecError::TrailingBytes(1)));
+
+        for len in 1..bytes.len() {
+            assert!(decode::<IndexedBlock>(&bytes[..len]).is_err());
+        }
+
+        // Corrupted input never panics, and whatever still decodes has the same encoding.
+        for _ in 0..1000 {
+            let mut corrupted = bytes.clone();
+            for _ in 0..=rng.below(4) {
+                let index = 1 + rng.below(corrupted.len() as u64 - 1) as usize;
+                corrupted[index] = rng.next() as u8;
+            }
+            if let Ok(block) = decode::<IndexedBlock>(&corrupted) {
+                assert_eq!(encode(&block), corrupted);
+            }
+        }
+        for _ in 0..1000 {
+            let mut garbage = vec![CODEC_VERSION];
+            garbage.extend(rng.bytes(64));
+            let _ = decode::<IndexedBlock>(&garbage);
+        }
+    }
+
+    #[test]
+    fn test_hash_excludes_version() {
+        let mut rng = Rng(0x6a09_e667_f3bc_c908);
+        for _ in 0..10 {
+            let block = block(&mut rng);
+            let header = block.header();
+            assert_eq!(header.hash(), blake2b_256(&encode(header)[1..]));
+            for tx in block.commit_transactions() {
+                assert_eq!(tx.hash(), blake2b_256(&encode(tx.transaction())[1..]));
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::cmp;
+
+use super::{ChainStore, Header, Shared, StoreError};
+
+/// The difficulty changes at most by this factor between two blocks.
+pub const MAX_ADJUSTMENT_FACTOR: u64 = 4;
+
+const RATE_PRECISION: u128 = 1_000_000;
+
+impl<S: ChainStore> Shared<S> {
+    /// Calculates the difficulty of the child of `parent`.
+    ///
+    /// The window is the last `pow_time_span / pow_spacing` blocks up to `parent`. The parent
+    /// difficulty is scaled by the expected over the actual time spent mining the window, and by
+    /// the orphan rate of the window, measured by uncles, over `orphan_rate_target`, so the
+    /// difficulty rises when blocks are found too fast or too many of them become uncles.
+    pub fn calculate_difficulty(&self, parent: &Header) -> Result<u64, StoreError> {
+        let consensus = &self.consensus;
+        let interval = cmp::max(consensus.pow_time_span / cmp::max(consensus.pow_spacing, 1), 1);
+
+        let mut start = parent.clone();
+        let mut blocks = 0u64;
+        let mut uncles = 0u64;
+        while blocks < interval && start.number > 0 {
+            uncles += u64::from(start.uncles_count);
+            match self.store.get_header(&start.parent_hash)? {
+                Some(header) => start = header,
+                None => break,
+            }
+            blocks += 1;
+        }
+        if blocks == 0 {
+            return Ok(parent.difficulty);
+        }
+
+        let expected_time = u128::from(blocks * consensus.pow_spacing);
+        let actual_time = u128::from(cmp::max(parent.timestamp.saturating_sub(start.timestamp), 1));
+        let target_rate = (f64::from(consensus.orphan_rate_target) * RATE_PRECISION as f64) as u128;
+        // (1 + orphan_rate) / (1 + orphan_rate_target)
+        let orphan_numerator = u128::from(blocks + uncles) * RATE_PRECISION;
+        let orphan_denominator = u128::from(blocks) * (RATE_PRECISION + target_rate);
+
+        let difficulty = u128::from(parent.difficulty) * expected_time * orphan_numerator
+            / (actual_time * orphan_denominator);
+
+        let min = cmp::max(parent.difficulty / MAX_ADJUSTMENT_FACTOR, 1);
+        let max = parent.difficulty.saturating_mul(MAX_ADJUSTMENT_FACTOR);
+        Ok(cmp::min(cmp::max(difficulty, u128::from(min)), u128::from(cmp::max(max, min))) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{Consensus, IndexedBlock, MemoryStore, StoreBatch, H256};
+
+    /// Stores a chain of 20 headers and returns the hash of the last one.
+    fn new_shared(uncles_count: u32) -> (Shared<MemoryStore>, H256) {
+        let mut consensus = Consensus {
+            pow_time_span: 100,
+            pow_spacing: 10,
+            orphan_rate_target: 0.1,
+            ..Consensus::default()
+        };
+        let mut batch = StoreBatch::default();
+        let mut parent_hash = H256::default();
+        for number in 0..20 {
+            let header = Header {
+                parent_hash,
+                number,
+                uncles_count,
+                ..Header::default()
+            };
+            parent_hash = header.hash();
+            let block = IndexedBlock::new(header, vec![], vec![], vec![]);
+            batch.insert_block(&block);
+            batch.attach_block(&block);
+            if number == 0 {
+                consensus.genesis_block = block;
+            }
+        }
+        batch.insert_tip(parent_hash);
+        let store = MemoryStore::default();
+        store.write(batch).expect("write should be ok");
+        (Shared::new(consensus, store).expect("init should be ok"), parent_hash)
+    }
+
+    fn parent(parent_hash: H256, timestamp: u64) -> Header {
+        Header {
+            parent_hash,
+            number: 20,
+            timestamp,
+            difficulty: 1000,
+            ..Header::default()
+        }
+    }
+
+    #[test]
+    fn test_difficulty() {
+        // 10 blocks, 1 uncle, spent the expected 100ms
+        let (shared, hash) = new_shared(0);
+        let mut header = parent(hash, 100);
+        header.uncles_count = 1;
+        assert_eq!(shared.calculate_difficulty(&header), Ok(1000));
+
+        // Twice as fast
+        assert_eq!(shared.calculate_difficulty(&parent(hash, 50)), Ok(1818));
+
+        // Clamped
+        assert_eq!(shared.calculate_difficulty(&parent(hash, 1)), Ok(4000));
+        assert_eq!(shared.calculate_difficulty(&parent(hash, 10000)), Ok(250));
+
+        // More uncles than the target
+        let (shared, hash) = new_shared(1);
+        assert_eq!(shared.calculate_difficulty(&parent(hash, 100)), Ok(1727));
+    }
+}
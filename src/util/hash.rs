@@ -0,0 +1,135 @@
+use std::fmt;
+use std::str::FromStr;
+
+use blake2_rfc::blake2b::Blake2b;
+
+const HASH_LEN: usize = 32;
+
+/// A 32 bytes hash, displayed as lowercase hex.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub struct H256([u8; HASH_LEN]);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum H256Error {
+    /// The input is not 32 bytes, or 64 hex digits
+    InvalidLength(usize),
+    InvalidHexCharacter { character: char, index: usize },
+}
+
+impl fmt::Display for H256Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            H256Error::InvalidLength(len) => write!(f, "invalid length {}", len),
+            H256Error::InvalidHexCharacter { character, index } => {
+                write!(f, "invalid hex character {:?} at {}", character, index)
+            }
+        }
+    }
+}
+
+impl H256 {
+    pub fn zero() -> Self {
+        H256::default()
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, H256Error> {
+        if bytes.len() != HASH_LEN {
+            return Err(H256Error::InvalidLength(bytes.len()));
+        }
+        let mut hash = [0u8; HASH_LEN];
+        hash.copy_from_slice(bytes);
+        Ok(H256(hash))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; HASH_LEN]> for H256 {
+    fn from(bytes: [u8; HASH_LEN]) -> Self {
+        H256(bytes)
+    }
+}
+
+impl fmt::Display for H256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for H256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Parses 64 hex digits, with an optional `0x` prefix.
+impl FromStr for H256 {
+    type Err = H256Error;
+
+    fn from_str(s: &str) -> Result<Self, H256Error> {
+        let offset = if s.starts_with("0x") { 2 } else { 0 };
+        let digits = &s.as_bytes()[offset..];
+        if digits.len() != HASH_LEN * 2 {
+            return Err(H256Error::InvalidLength(digits.len()));
+        }
+        let mut hash = [0u8; HASH_LEN];
+        for (index, digit) in digits.iter().enumerate() {
+            let value = (*digit as char).to_digit(16).ok_or_else(|| {
+                H256Error::InvalidHexCharacter {
+                    character: s[offset + index..].chars().next().unwrap_or('?'),
+                    index: offset + index,
+                }
+            })?;
+            hash[index / 2] |= (value as u8) << if index % 2 == 0 { 4 } else { 0 };
+        }
+        Ok(H256(hash))
+    }
+}
+
+/// The 32 bytes blake2b digest of `data`.
+pub fn blake2b_256(data: &[u8]) -> H256 {
+    let mut hasher = Blake2b::new(HASH_LEN);
+    hasher.update(data);
+    H256::from_slice(hasher.finalize().as_bytes()).expect("digest should be 32 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        let hex = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1fff";
+        let hash: H256 = hex.parse().expect("parse should be ok");
+        assert_eq!(hash.as_bytes()[0], 1);
+        assert_eq!(hash.as_bytes()[31], 0xff);
+        assert_eq!(hash.to_string(), hex);
+        assert_eq!(format!("0x{}", hex).parse(), Ok(hash));
+        assert_eq!(hex.to_uppercase().parse(), Ok(hash));
+
+        assert_eq!("0x01".parse::<H256>(), Err(H256Error::InvalidLength(2)));
+        assert_eq!(
+            format!("{}g", &hex[..63]).parse::<H256>(),
+            Err(H256Error::InvalidHexCharacter {
+                character: 'g',
+                index: 63
+            })
+        );
+        assert_eq!(H256::from_slice(hash.as_bytes()), Ok(hash));
+        assert_eq!(H256::from_slice(&[0; 31]), Err(H256Error::InvalidLength(31)));
+        assert!(H256::zero() < hash);
+    }
+
+    #[test]
+    fn test_blake2b_256() {
+        assert_eq!(
+            blake2b_256(b"").to_string(),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use channel::Sender;
+use parking_lot::{Mutex, RwLock};
+
+use verification::VerificationCache;
+
+mod block;
+pub mod codec;
+mod difficulty;
+mod hash;
+mod pow;
+mod store;
+
+// Not every re-export is used by the binary yet
+#[allow(unused_imports)]
+pub use self::block::{
+    Header,
+    IndexedBlock,
+    IndexedTransaction,
+    Transaction,
+    OutPoint,
+    CellInput,
+    CellOutput,
+    transactions_root,
+    uncles_hash,
+};
+#[allow(unused_imports)]
+pub use self::hash::{H256, H256Error, blake2b_256};
+#[allow(unused_imports)]
+pub use self::pow::{
+    PowEngine,
+    Pow,
+    Cuckoo,
+    CuckooEngine,
+    DummyPowEngine,
+    DelayPowEngine,
+    RejectPowEngine,
+};
+#[allow(unused_imports)]
+pub use self::store::{
+    ChainStore,
+    StoreBatch,
+    StoreError,
+    BlockExt,
+    TransactionAddress,
+    RocksDBStore,
+    MemoryStore,
+    MemorySnapshot,
+};
+
+pub type Capacity = u64;
+pub type BlockNumber = u64;
+
+#[derive(Debug)]
+pub struct Shared<S> {
+    pub consensus: Consensus,
+    pub store: Arc<S>,
+    pub verification_cache: Arc<Mutex<VerificationCache>>,
+    /// The main chain tip, shared so it can be read without asking `ChainService`
+    pub tip: Arc<RwLock<TipHeader>>,
+}
+
+// Derived `Clone` would require `S: Clone`.
+impl<S> Clone for Shared<S> {
+    fn clone(&self) -> Self {
+        Shared {
+            consensus: self.consensus.clone(),
+            store: Arc::clone(&self.store),
+            verification_cache: Arc::clone(&self.verification_cache),
+            tip: Arc::clone(&self.tip),
+        }
+    }
+}
+
+/// Milliseconds since the unix epoch.
+pub fn now_ms() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch");
+    now.as_secs() * 1000 + u64::from(now.subsec_millis())
+}
+
+pub struct Request<A, R> {
+    pub responsor: Sender<R>,
+    pub arguments: A,
+}
+
+impl<S: ChainStore> Shared<S> {
+    /// Writes the genesis block into an empty store, or checks the stored one matches
+    /// `consensus`, refusing to share a chain of another network.
+    pub fn new(consensus: Consensus, store: S) -> Result<Self, StoreError> {
+        init_genesis(&consensus.genesis_block, &store)?;
+        let tip = match store.get_tip()? {
+            Some(hash) => match (store.get_header(&hash)?, store.get_block_ext(&hash)?) {
+                (Some(header), Some(ext)) => TipHeader::new(&header, ext.total_difficulty),
+                _ => TipHeader::default(),
+            },
+            None => TipHeader::default(),
+        };
+        Ok(Shared {
+            consensus,
+            store: Arc::new(store),
+            verification_cache: Arc::new(Mutex::new(VerificationCache::default())),
+            tip: Arc::new(RwLock::new(tip)),
+        })
+    }
+}
+
+fn init_genesis<S: ChainStore>(genesis: &IndexedBlock, store: &S) -> Result<(), StoreError> {
+    let expected = genesis.hash();
+    if store.get_tip()?.is_some() {
+        let actual = store.get_block_hash(0)?.ok_or(StoreError::MissingGenesis)?;
+        if actual != expected {
+            return Err(StoreError::GenesisMismatch { expected, actual });
+        }
+        return Ok(());
+    }
+
+    let mut batch = StoreBatch::default();
+    batch.insert_block(genesis);
+    batch.insert_block_ext(
+        expected,
+        BlockExt {
+            total_difficulty: genesis.header().difficulty,
+        },
+    );
+    batch.attach_block(genesis);
+    batch.insert_tip(expected);
+    store.write(batch)
+}
+
+impl<S> Shared<S> {
+    pub fn tip_header(&self) -> TipHeader {
+        *self.tip.read()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TipHeader {
+    hash: H256,
+    number: BlockNumber,
+    total_difficulty: u64,
+    timestamp: u64,
+}
+
+impl TipHeader {
+    pub fn new(header: &Header, total_difficulty: u64) -> Self {
+        TipHeader {
+            hash: header.hash(),
+            number: header.number,
+            total_difficulty,
+            timestamp: header.timestamp,
+        }
+    }
+
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    pub fn number(&self) -> BlockNumber {
+        self.number
+    }
+
+    pub fn total_difficulty(&self) -> u64 {
+        self.total_difficulty
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Consensus {
+    pub genesis_block: IndexedBlock,
+    pub initial_block_reward: Capacity,
+    pub max_uncles_age: usize,
+    pub max_uncles_len: usize,
+    pub orphan_rate_target: f32,
+    pub pow_time_span: u64,
+    pub pow_spacing: u64,
+    pub transaction_propagation_time: BlockNumber,
+    pub transaction_propagation_timeout: BlockNumber,
+    pub pow: Pow,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InsertionResult {
+    /// Added to the pool, waiting to be proposed
+    Pending,
+    /// Added to the pool as pending, replacing the conflicting transactions and their
+    /// descendants, which were evicted
+    Replaced(Vec<H256>),
+    /// Some inputs or deps are unknown, kept apart until their transactions are added
+    Orphan,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_genesis() {
+        let header = Header {
+            difficulty: 100,
+            ..Header::default()
+        };
+        let consensus = Consensus {
+            genesis_block: IndexedBlock::new(header, vec![], vec![], vec![]),
+            ..Consensus::default()
+        };
+        let genesis = consensus.genesis_block.clone();
+
+        let shared =
+            Shared::new(consensus.clone(), MemoryStore::default()).expect("init should be ok");
+        assert_eq!(shared.store.get_block(&genesis.hash()), Ok(Some(genesis.clone())));
+        assert_eq!(shared.store.get_block_hash(0), Ok(Some(genesis.hash())));
+        assert_eq!(shared.tip_header(), TipHeader::new(genesis.header(), 100));
+
+        // Reopening keeps the chain
+        let store = MemoryStore::from_snapshot(shared.store.snapshot());
+        assert!(Shared::new(consensus.clone(), store).is_ok());
+
+        // But refuses another genesis
+        let store = MemoryStore::from_snapshot(shared.store.snapshot());
+        let other = Consensus {
+            genesis_block: IndexedBlock::default(),
+            ..consensus
+        };
+        match Shared::new(other, store) {
+            Err(StoreError::GenesisMismatch { expected, actual }) => {
+                assert_eq!(expected, IndexedBlock::default().hash());
+                assert_eq!(actual, genesis.hash());
+            }
+            _ => panic!("genesis mismatch expected"),
+        }
+    }
+}
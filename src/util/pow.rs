@@ -0,0 +1,445 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::{BlockNumber, Header};
+
+pub const DEFAULT_EDGE_BITS: u8 = 15;
+pub const DEFAULT_CYCLE_LENGTH: usize = 12;
+
+const NIL: u64 = u64::MAX;
+
+pub trait PowEngine {
+    fn init(&self, _number: BlockNumber) {}
+
+    /// Searches a proof for the header with the nonce, which replaces `header.nonce`.
+    fn solve(&self, header: &Header, nonce: u64) -> Option<Vec<u32>>;
+
+    /// Verifies the proof for the header and `header.nonce`.
+    fn verify(&self, header: &Header, proof: &[u32]) -> bool;
+
+    fn verify_header(&self, header: &Header) -> bool {
+        self.verify(header, &header.proof)
+    }
+}
+
+impl<P: PowEngine + ?Sized> PowEngine for Arc<P> {
+    fn init(&self, number: BlockNumber) {
+        (**self).init(number)
+    }
+
+    fn solve(&self, header: &Header, nonce: u64) -> Option<Vec<u32>> {
+        (**self).solve(header, nonce)
+    }
+
+    fn verify(&self, header: &Header, proof: &[u32]) -> bool {
+        (**self).verify(header, proof)
+    }
+}
+
+/// Selects the `PowEngine`, the engines other than `Cuckoo` are meant for tests.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Pow {
+    Cuckoo { edge_bits: u8, cycle_length: usize },
+    /// See `DummyPowEngine`
+    Dummy,
+    /// See `DelayPowEngine`, the delay is in milliseconds
+    Delay(u64),
+    /// See `RejectPowEngine`
+    Reject(Vec<u64>),
+}
+
+impl Default for Pow {
+    fn default() -> Self {
+        Pow::Cuckoo {
+            edge_bits: DEFAULT_EDGE_BITS,
+            cycle_length: DEFAULT_CYCLE_LENGTH,
+        }
+    }
+}
+
+impl Pow {
+    pub fn engine(&self) -> Arc<dyn PowEngine + Send + Sync> {
+        match *self {
+            Pow::Cuckoo {
+                edge_bits,
+                cycle_length,
+            } => Arc::new(CuckooEngine::new(Cuckoo::new(edge_bits, cycle_length))),
+            Pow::Dummy => Arc::new(DummyPowEngine::default()),
+            Pow::Delay(millis) => Arc::new(DelayPowEngine::new(Duration::from_millis(millis))),
+            Pow::Reject(ref nonces) => Arc::new(RejectPowEngine::new(nonces.iter().cloned())),
+        }
+    }
+}
+
+/// Cuckoo cycle on a bipartite graph with `max_vertex / 2` vertices on each side.
+///
+/// Edge `i` connects the even vertex `2 * (siphash(2i) % (max_vertex / 2))` and the odd vertex
+/// `2 * (siphash(2i + 1) % (max_vertex / 2)) + 1`. A proof is the ascending edge indices of a
+/// cycle of `cycle_length` edges.
+#[derive(Clone, Debug)]
+pub struct Cuckoo {
+    max_vertex: usize,
+    max_edge: usize,
+    cycle_length: usize,
+}
+
+impl Default for Cuckoo {
+    fn default() -> Self {
+        Cuckoo::new(DEFAULT_EDGE_BITS, DEFAULT_CYCLE_LENGTH)
+    }
+}
+
+impl Cuckoo {
+    /// Creates a graph of `2^edge_bits` edges and `2^(edge_bits + 1)` vertices.
+    pub fn new(edge_bits: u8, cycle_length: usize) -> Self {
+        assert!(cycle_length >= 2 && cycle_length.is_multiple_of(2), "Cycle length must be even");
+        let max_edge = 1 << edge_bits;
+        Cuckoo {
+            max_vertex: max_edge << 1,
+            max_edge,
+            cycle_length,
+        }
+    }
+
+    pub fn cycle_length(&self) -> usize {
+        self.cycle_length
+    }
+
+    fn edge(&self, keys: &[u64; 4], index: usize) -> (u64, u64) {
+        let half = (self.max_vertex >> 1) as u64;
+        let index = index as u64;
+        let u = (siphash24(keys, 2 * index) % half) << 1;
+        let v = ((siphash24(keys, 2 * index + 1) % half) << 1) | 1;
+        (u, v)
+    }
+
+    pub fn verify(&self, message: &[u8], proof: &[u32]) -> bool {
+        if proof.len() != self.cycle_length {
+            return false;
+        }
+        if proof.windows(2).any(|pair| pair[0] >= pair[1])
+            || proof[proof.len() - 1] as usize >= self.max_edge
+        {
+            return false;
+        }
+
+        let keys = message_keys(message);
+        let edges: Vec<(u64, u64)> = proof
+            .iter()
+            .map(|&index| self.edge(&keys, index as usize))
+            .collect();
+        let endpoint = |index: usize, odd: bool| {
+            if odd {
+                edges[index].1
+            } else {
+                edges[index].0
+            }
+        };
+
+        // Walk the cycle from the first edge, alternating between even and odd vertices. Every
+        // vertex must be shared by exactly two edges.
+        let mut current = 0;
+        let mut odd = false;
+        let mut steps = 0;
+        loop {
+            let vertex = endpoint(current, odd);
+            let mut next = None;
+            for other in 0..edges.len() {
+                if other != current && endpoint(other, odd) == vertex {
+                    if next.is_some() {
+                        return false;
+                    }
+                    next = Some(other);
+                }
+            }
+            match next {
+                Some(other) => current = other,
+                None => return false,
+            }
+            odd = !odd;
+            steps += 1;
+            if current == 0 {
+                break;
+            }
+        }
+        steps == self.cycle_length
+    }
+
+    /// Adds the edges in order and keeps the graph as a forest by reversing paths towards the
+    /// roots. An edge joining two vertices in the same tree closes a cycle.
+    pub fn solve(&self, message: &[u8]) -> Option<Vec<u32>> {
+        let keys = message_keys(message);
+        let mut graph = vec![NIL; self.max_vertex];
+
+        for index in 0..self.max_edge {
+            let (u, v) = self.edge(&keys, index);
+            let us = path(&graph, u);
+            let vs = path(&graph, v);
+            if us.last() == vs.last() {
+                let (mut nu, mut nv) = (us.len() - 1, vs.len() - 1);
+                while nu > 0 && nv > 0 && us[nu - 1] == vs[nv - 1] {
+                    nu -= 1;
+                    nv -= 1;
+                }
+                if nu + nv + 1 == self.cycle_length {
+                    return Some(self.recover(&keys, index, &us[..=nu], &vs[..=nv]));
+                }
+            } else if us.len() < vs.len() {
+                reverse(&mut graph, &us);
+                graph[u as usize] = v;
+            } else {
+                reverse(&mut graph, &vs);
+                graph[v as usize] = u;
+            }
+        }
+        None
+    }
+
+    /// Finds the edge indices of the cycle closed by the edge `last`.
+    fn recover(&self, keys: &[u64; 4], last: usize, us: &[u64], vs: &[u64]) -> Vec<u32> {
+        let mut cycle = HashSet::with_capacity(self.cycle_length);
+        let mut insert = |a: u64, b: u64| {
+            let pair = if a & 1 == 0 { (a, b) } else { (b, a) };
+            cycle.insert(pair);
+        };
+        insert(us[0], vs[0]);
+        for path in &[us, vs] {
+            for pair in path.windows(2) {
+                insert(pair[0], pair[1]);
+            }
+        }
+
+        let mut proof = Vec::with_capacity(self.cycle_length);
+        for index in 0..=last {
+            if cycle.remove(&self.edge(keys, index)) {
+                proof.push(index as u32);
+            }
+        }
+        proof
+    }
+}
+
+fn path(graph: &[u64], mut vertex: u64) -> Vec<u64> {
+    let mut path = vec![vertex];
+    while graph[vertex as usize] != NIL {
+        vertex = graph[vertex as usize];
+        path.push(vertex);
+    }
+    path
+}
+
+fn reverse(graph: &mut [u64], path: &[u64]) {
+    for pair in path.windows(2).rev() {
+        graph[pair[1] as usize] = pair[0];
+    }
+}
+
+/// Derives the siphash keys from the message.
+fn message_keys(message: &[u8]) -> [u64; 4] {
+    let mut keys = [
+        0x736f_6d65_7073_6575,
+        0x646f_7261_6e64_6f6d,
+        0x6c79_6765_6e65_7261,
+        0x7465_6462_7974_6573,
+    ];
+    for (i, chunk) in message.chunks(8).enumerate() {
+        let mut word = 0u64;
+        for (shift, byte) in chunk.iter().enumerate() {
+            word |= u64::from(*byte) << (8 * shift);
+        }
+        keys[i % 4] ^= siphash24(&keys, word);
+    }
+    let mixed = keys;
+    for (i, key) in keys.iter_mut().enumerate() {
+        *key = siphash24(&mixed, i as u64);
+    }
+    keys
+}
+
+fn siphash24(keys: &[u64; 4], nonce: u64) -> u64 {
+    let mut v = [keys[0], keys[1], keys[2], keys[3] ^ nonce];
+    sip_round(&mut v);
+    sip_round(&mut v);
+    v[0] ^= nonce;
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[1] = v[1].rotate_left(13);
+    v[3] = v[3].rotate_left(16);
+    v[1] ^= v[0];
+    v[3] ^= v[2];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[1]);
+    v[0] = v[0].wrapping_add(v[3]);
+    v[1] = v[1].rotate_left(17);
+    v[3] = v[3].rotate_left(21);
+    v[1] ^= v[2];
+    v[3] ^= v[0];
+    v[2] = v[2].rotate_left(32);
+}
+
+#[derive(Clone, Default)]
+pub struct CuckooEngine {
+    cuckoo: Cuckoo,
+}
+
+impl CuckooEngine {
+    pub fn new(cuckoo: Cuckoo) -> Self {
+        CuckooEngine { cuckoo }
+    }
+}
+
+impl PowEngine for CuckooEngine {
+    fn solve(&self, header: &Header, nonce: u64) -> Option<Vec<u32>> {
+        self.cuckoo.solve(&header.pow_message(nonce))
+    }
+
+    fn verify(&self, header: &Header, proof: &[u32]) -> bool {
+        self.cuckoo.verify(&header.pow_message(header.nonce), proof)
+    }
+}
+
+/// Solves every nonce with an empty proof and accepts any proof.
+#[derive(Clone, Default)]
+pub struct DummyPowEngine {}
+
+impl PowEngine for DummyPowEngine {
+    fn solve(&self, _header: &Header, _nonce: u64) -> Option<Vec<u32>> {
+        Some(Vec::new())
+    }
+
+    fn verify(&self, _header: &Header, _proof: &[u32]) -> bool {
+        true
+    }
+}
+
+/// Like `DummyPowEngine`, but sleeps for the delay in `solve` to simulate mining time.
+#[derive(Clone)]
+pub struct DelayPowEngine {
+    delay: Duration,
+}
+
+impl DelayPowEngine {
+    pub fn new(delay: Duration) -> Self {
+        DelayPowEngine { delay }
+    }
+}
+
+impl PowEngine for DelayPowEngine {
+    fn solve(&self, _header: &Header, _nonce: u64) -> Option<Vec<u32>> {
+        thread::sleep(self.delay);
+        Some(Vec::new())
+    }
+
+    fn verify(&self, _header: &Header, _proof: &[u32]) -> bool {
+        true
+    }
+}
+
+/// Like `DummyPowEngine`, but fails to solve and rejects the listed nonces.
+#[derive(Clone, Default)]
+pub struct RejectPowEngine {
+    nonces: HashSet<u64>,
+}
+
+impl RejectPowEngine {
+    pub fn new<I: IntoIterator<Item = u64>>(nonces: I) -> Self {
+        RejectPowEngine {
+            nonces: nonces.into_iter().collect(),
+        }
+    }
+}
+
+impl PowEngine for RejectPowEngine {
+    fn solve(&self, _header: &Header, nonce: u64) -> Option<Vec<u32>> {
+        if self.nonces.contains(&nonce) {
+            None
+        } else {
+            Some(Vec::new())
+        }
+    }
+
+    fn verify(&self, header: &Header, _proof: &[u32]) -> bool {
+        !self.nonces.contains(&header.nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_solve_and_verify() {
+        let engine = CuckooEngine::new(Cuckoo::new(6, 6));
+        let mut header = Header::default();
+        let (nonce, proof) = (0..1000)
+            .filter_map(|nonce| engine.solve(&header, nonce).map(|proof| (nonce, proof)))
+            .next()
+            .expect("no cycle in 1000 graphs");
+        assert_eq!(proof.len(), 6);
+
+        header.nonce = nonce;
+        assert!(engine.verify(&header, &proof));
+
+        let mut unordered = proof.clone();
+        unordered.swap(0, 1);
+        assert!(!engine.verify(&header, &unordered));
+        assert!(!engine.verify(&header, &proof[1..]));
+        let mut out_of_range = proof.clone();
+        out_of_range[5] = 64;
+        assert!(!engine.verify(&header, &out_of_range));
+
+        header.nonce = nonce + 1;
+        assert!(!engine.verify(&header, &proof));
+    }
+
+    #[test]
+    fn test_dummy_engine() {
+        let engine = Pow::Dummy.engine();
+        let header = Header {
+            nonce: 42,
+            proof: vec![1, 2, 3],
+            ..Header::default()
+        };
+        assert_eq!(engine.solve(&header, 0), Some(vec![]));
+        assert!(engine.verify_header(&header));
+        assert!(engine.verify(&header, &[]));
+    }
+
+    #[test]
+    fn test_delay_engine() {
+        let delay = Duration::from_millis(50);
+        let engine = DelayPowEngine::new(delay);
+        let header = Header::default();
+
+        let start = Instant::now();
+        assert_eq!(engine.solve(&header, 0), Some(vec![]));
+        assert!(start.elapsed() >= delay);
+
+        // Only mining is delayed
+        let start = Instant::now();
+        assert!(engine.verify_header(&header));
+        assert!(start.elapsed() < delay);
+    }
+
+    #[test]
+    fn test_reject_engine() {
+        let engine = Pow::Reject(vec![1, 3]).engine();
+        let mut header = Header::default();
+        assert_eq!(engine.solve(&header, 0), Some(vec![]));
+        assert_eq!(engine.solve(&header, 1), None);
+        assert!(engine.verify_header(&header));
+        header.nonce = 3;
+        assert!(!engine.verify_header(&header));
+    }
+}
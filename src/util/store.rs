@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use rocksdb::{self, ColumnFamily, Options, WriteBatch, DB};
+use serde::de::DeserializeOwned;
+
+use super::codec::{decode, encode, CodecError};
+use super::{BlockNumber, Header, IndexedBlock, IndexedTransaction, OutPoint, H256};
+
+/// Bumped on every incompatible change of the database layout.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub const COLUMN_BLOCK_HEADER: &str = "block_header";
+pub const COLUMN_BLOCK_UNCLES: &str = "block_uncles";
+pub const COLUMN_BLOCK_TRANSACTIONS: &str = "block_transactions";
+pub const COLUMN_BLOCK_PROPOSAL_TRANSACTIONS: &str = "block_proposal_transactions";
+pub const COLUMN_BLOCK_EXT: &str = "block_ext";
+pub const COLUMN_INDEX: &str = "index";
+pub const COLUMN_TRANSACTION_ADDRESS: &str = "transaction_address";
+pub const COLUMN_CELL_SPENDER: &str = "cell_spender";
+pub const COLUMN_META: &str = "meta";
+
+pub const COLUMNS: [&str; 9] = [
+    COLUMN_BLOCK_HEADER,
+    COLUMN_BLOCK_UNCLES,
+    COLUMN_BLOCK_TRANSACTIONS,
+    COLUMN_BLOCK_PROPOSAL_TRANSACTIONS,
+    COLUMN_BLOCK_EXT,
+    COLUMN_INDEX,
+    COLUMN_TRANSACTION_ADDRESS,
+    COLUMN_CELL_SPENDER,
+    COLUMN_META,
+];
+
+const META_TIP_KEY: &[u8] = b"tip";
+const META_SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreError {
+    /// Error reported by the database
+    Database(String),
+    /// A stored value cannot be decoded
+    Codec(CodecError),
+    /// The database was created by an incompatible version
+    SchemaVersion { expected: u32, actual: u32 },
+    /// The database holds a chain with another genesis block
+    GenesisMismatch { expected: H256, actual: H256 },
+    /// The database has a tip but no genesis block
+    MissingGenesis,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StoreError::Database(ref err) => write!(f, "database error: {}", err),
+            StoreError::Codec(ref err) => write!(f, "codec error: {}", err),
+            StoreError::SchemaVersion { expected, actual } => write!(
+                f,
+                "schema version mismatch: expected {}, actual {}",
+                expected, actual
+            ),
+            StoreError::GenesisMismatch { expected, actual } => write!(
+                f,
+                "genesis mismatch: expected {}, actual {}",
+                expected, actual
+            ),
+            StoreError::MissingGenesis => write!(f, "missing genesis block"),
+        }
+    }
+}
+
+impl From<rocksdb::Error> for StoreError {
+    fn from(err: rocksdb::Error) -> Self {
+        StoreError::Database(err.to_string())
+    }
+}
+
+impl From<CodecError> for StoreError {
+    fn from(err: CodecError) -> Self {
+        StoreError::Codec(err)
+    }
+}
+
+/// Where a main chain transaction is committed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TransactionAddress {
+    pub block_hash: H256,
+    pub index: u32,
+}
+
+/// Block data derived from the chain, stored for blocks in both main and side chains.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct BlockExt {
+    /// The sum of the difficulties from genesis to the block
+    pub total_difficulty: u64,
+}
+
+pub enum BatchOperation {
+    InsertBlock(Box<IndexedBlock>),
+    InsertBlockExt(H256, BlockExt),
+    AttachBlock {
+        number: BlockNumber,
+        hash: H256,
+        transactions: Vec<H256>,
+        /// Outputs spent by the block and the spending transactions
+        spent: Vec<(OutPoint, H256)>,
+    },
+    DetachBlock {
+        number: BlockNumber,
+        transactions: Vec<H256>,
+        spent: Vec<OutPoint>,
+    },
+    InsertTip(H256),
+}
+
+/// Writes applied atomically by `ChainStore::write`.
+#[derive(Default)]
+pub struct StoreBatch {
+    operations: Vec<BatchOperation>,
+}
+
+impl StoreBatch {
+    pub fn insert_block(&mut self, block: &IndexedBlock) {
+        self.operations.push(BatchOperation::InsertBlock(Box::new(block.clone())));
+    }
+
+    pub fn insert_block_ext(&mut self, hash: H256, ext: BlockExt) {
+        self.operations.push(BatchOperation::InsertBlockExt(hash, ext));
+    }
+
+    /// Adds the block to the main chain, which indexes the number, the transactions and the
+    /// outputs they spend.
+    pub fn attach_block(&mut self, block: &IndexedBlock) {
+        self.operations.push(BatchOperation::AttachBlock {
+            number: block.number(),
+            hash: block.hash(),
+            transactions: block.commit_transactions().iter().map(|tx| tx.hash()).collect(),
+            spent: spent_out_points(block)
+                .map(|(out_point, tx)| (out_point, tx.hash()))
+                .collect(),
+        });
+    }
+
+    /// Removes the block from the main chain, the block itself is kept.
+    pub fn detach_block(&mut self, block: &IndexedBlock) {
+        self.operations.push(BatchOperation::DetachBlock {
+            number: block.number(),
+            transactions: block.commit_transactions().iter().map(|tx| tx.hash()).collect(),
+            spent: spent_out_points(block).map(|(out_point, _)| out_point).collect(),
+        });
+    }
+
+    pub fn insert_tip(&mut self, hash: H256) {
+        self.operations.push(BatchOperation::InsertTip(hash));
+    }
+
+    pub fn into_operations(self) -> Vec<BatchOperation> {
+        self.operations
+    }
+}
+
+pub trait ChainStore: Send + Sync {
+    fn get_header(&self, hash: &H256) -> Result<Option<Header>, StoreError>;
+
+    fn get_block_uncles(&self, hash: &H256) -> Result<Option<Vec<Header>>, StoreError>;
+
+    fn get_block_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError>;
+
+    fn get_block_proposal_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError>;
+
+    fn get_block_ext(&self, hash: &H256) -> Result<Option<BlockExt>, StoreError>;
+
+    /// The hash of the main chain block of the number.
+    fn get_block_hash(&self, number: BlockNumber) -> Result<Option<H256>, StoreError>;
+
+    fn get_transaction_address(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<TransactionAddress>, StoreError>;
+
+    /// The main chain transaction spending the output.
+    fn get_cell_spender(&self, out_point: &OutPoint) -> Result<Option<H256>, StoreError>;
+
+    fn get_tip(&self) -> Result<Option<H256>, StoreError>;
+
+    fn write(&self, batch: StoreBatch) -> Result<(), StoreError>;
+
+    fn get_block(&self, hash: &H256) -> Result<Option<IndexedBlock>, StoreError> {
+        let header = match self.get_header(hash)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        match (
+            self.get_block_uncles(hash)?,
+            self.get_block_transactions(hash)?,
+            self.get_block_proposal_transactions(hash)?,
+        ) {
+            (Some(uncles), Some(transactions), Some(proposals)) => Ok(Some(IndexedBlock::new(
+                header,
+                uncles,
+                transactions,
+                proposals,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Gets a main chain transaction.
+    fn get_transaction(&self, hash: &H256) -> Result<Option<IndexedTransaction>, StoreError> {
+        let address = match self.get_transaction_address(hash)? {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+        Ok(self
+            .get_block_transactions(&address.block_hash)?
+            .and_then(|transactions| transactions.into_iter().nth(address.index as usize)))
+    }
+}
+
+/// Persists the chain in RocksDB, one column family per kind of record.
+pub struct RocksDBStore {
+    db: Arc<DB>,
+}
+
+impl RocksDBStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, path, &COLUMNS)?;
+        let store = RocksDBStore { db: Arc::new(db) };
+        store.check_schema_version()?;
+        Ok(store)
+    }
+
+    /// Writes the schema version into a new database, or compares it with the stored one.
+    ///
+    /// The version is stored as little endian bytes instead of with the codec, so it can be
+    /// read whatever the codec version is.
+    fn check_schema_version(&self) -> Result<(), StoreError> {
+        let meta = self.cf(COLUMN_META);
+        match self.db.get_cf(meta, META_SCHEMA_VERSION_KEY)? {
+            Some(value) => {
+                let actual = value
+                    .iter()
+                    .take(4)
+                    .enumerate()
+                    .fold(0u32, |version, (i, byte)| version | u32::from(*byte) << (8 * i));
+                if actual != SCHEMA_VERSION || value.len() != 4 {
+                    return Err(StoreError::SchemaVersion {
+                        expected: SCHEMA_VERSION,
+                        actual,
+                    });
+                }
+            }
+            None => {
+                self.db
+                    .put_cf(meta, META_SCHEMA_VERSION_KEY, &SCHEMA_VERSION.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cf(&self, name: &str) -> ColumnFamily {
+        self.db
+            .cf_handle(name)
+            .unwrap_or_else(|| panic!("column family {} should exist", name))
+    }
+
+    fn get<T: DeserializeOwned>(&self, column: &str, key: &[u8]) -> Result<Option<T>, StoreError> {
+        match self.db.get_cf(self.cf(column), key)? {
+            Some(value) => Ok(Some(decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ChainStore for RocksDBStore {
+    fn get_header(&self, hash: &H256) -> Result<Option<Header>, StoreError> {
+        self.get(COLUMN_BLOCK_HEADER, hash.as_bytes())
+    }
+
+    fn get_block_uncles(&self, hash: &H256) -> Result<Option<Vec<Header>>, StoreError> {
+        self.get(COLUMN_BLOCK_UNCLES, hash.as_bytes())
+    }
+
+    fn get_block_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError> {
+        self.get(COLUMN_BLOCK_TRANSACTIONS, hash.as_bytes())
+    }
+
+    fn get_block_proposal_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError> {
+        self.get(COLUMN_BLOCK_PROPOSAL_TRANSACTIONS, hash.as_bytes())
+    }
+
+    fn get_block_ext(&self, hash: &H256) -> Result<Option<BlockExt>, StoreError> {
+        self.get(COLUMN_BLOCK_EXT, hash.as_bytes())
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Result<Option<H256>, StoreError> {
+        self.get(COLUMN_INDEX, &number_key(number))
+    }
+
+    fn get_transaction_address(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<TransactionAddress>, StoreError> {
+        self.get(COLUMN_TRANSACTION_ADDRESS, hash.as_bytes())
+    }
+
+    fn get_cell_spender(&self, out_point: &OutPoint) -> Result<Option<H256>, StoreError> {
+        self.get(COLUMN_CELL_SPENDER, &out_point_key(out_point))
+    }
+
+    fn get_tip(&self) -> Result<Option<H256>, StoreError> {
+        self.get(COLUMN_META, META_TIP_KEY)
+    }
+
+    fn write(&self, batch: StoreBatch) -> Result<(), StoreError> {
+        let mut inner = WriteBatch::default();
+        for operation in batch.into_operations() {
+            match operation {
+                BatchOperation::InsertBlock(block) => {
+                    let hash = block.hash();
+                    let key = hash.as_bytes();
+                    inner.put_cf(self.cf(COLUMN_BLOCK_HEADER), key, &encode(block.header()))?;
+                    inner.put_cf(self.cf(COLUMN_BLOCK_UNCLES), key, &encode(block.uncles()))?;
+                    inner.put_cf(
+                        self.cf(COLUMN_BLOCK_TRANSACTIONS),
+                        key,
+                        &encode(block.commit_transactions()),
+                    )?;
+                    inner.put_cf(
+                        self.cf(COLUMN_BLOCK_PROPOSAL_TRANSACTIONS),
+                        key,
+                        &encode(block.proposal_transactions()),
+                    )?;
+                }
+                BatchOperation::InsertBlockExt(hash, ext) => {
+                    inner.put_cf(self.cf(COLUMN_BLOCK_EXT), hash.as_bytes(), &encode(&ext))?;
+                }
+                BatchOperation::AttachBlock {
+                    number,
+                    hash,
+                    transactions,
+                    spent,
+                } => {
+                    inner.put_cf(self.cf(COLUMN_INDEX), &number_key(number), &encode(&hash))?;
+                    for (out_point, tx_hash) in &spent {
+                        inner.put_cf(
+                            self.cf(COLUMN_CELL_SPENDER),
+                            &out_point_key(out_point),
+                            &encode(tx_hash),
+                        )?;
+                    }
+                    for (index, tx_hash) in transactions.iter().enumerate() {
+                        let address = TransactionAddress {
+                            block_hash: hash,
+                            index: index as u32,
+                        };
+                        inner.put_cf(
+                            self.cf(COLUMN_TRANSACTION_ADDRESS),
+                            tx_hash.as_bytes(),
+                            &encode(&address),
+                        )?;
+                    }
+                }
+                BatchOperation::DetachBlock {
+                    number,
+                    transactions,
+                    spent,
+                } => {
+                    inner.delete_cf(self.cf(COLUMN_INDEX), &number_key(number))?;
+                    for out_point in &spent {
+                        inner.delete_cf(self.cf(COLUMN_CELL_SPENDER), &out_point_key(out_point))?;
+                    }
+                    for tx_hash in &transactions {
+                        inner.delete_cf(self.cf(COLUMN_TRANSACTION_ADDRESS), tx_hash.as_bytes())?;
+                    }
+                }
+                BatchOperation::InsertTip(hash) => {
+                    inner.put_cf(self.cf(COLUMN_META), META_TIP_KEY, &encode(&hash))?;
+                }
+            }
+        }
+        self.db.write(inner)?;
+        Ok(())
+    }
+}
+
+/// The content of a `MemoryStore`, can be saved as a fixture and loaded by
+/// `MemoryStore::from_snapshot`.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct MemorySnapshot {
+    pub headers: HashMap<H256, Header>,
+    pub uncles: HashMap<H256, Vec<Header>>,
+    pub transactions: HashMap<H256, Vec<IndexedTransaction>>,
+    pub proposal_transactions: HashMap<H256, Vec<IndexedTransaction>>,
+    pub exts: HashMap<H256, BlockExt>,
+    pub index: HashMap<BlockNumber, H256>,
+    pub transaction_addresses: HashMap<H256, TransactionAddress>,
+    pub cell_spenders: HashMap<OutPoint, H256>,
+    pub tip: Option<H256>,
+}
+
+/// Keeps the chain in memory, for tests and nodes which do not need to persist the chain.
+#[derive(Default)]
+pub struct MemoryStore {
+    inner: RwLock<MemorySnapshot>,
+}
+
+impl MemoryStore {
+    pub fn from_snapshot(snapshot: MemorySnapshot) -> Self {
+        MemoryStore {
+            inner: RwLock::new(snapshot),
+        }
+    }
+
+    pub fn snapshot(&self) -> MemorySnapshot {
+        self.inner.read().clone()
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn get_header(&self, hash: &H256) -> Result<Option<Header>, StoreError> {
+        Ok(self.inner.read().headers.get(hash).cloned())
+    }
+
+    fn get_block_uncles(&self, hash: &H256) -> Result<Option<Vec<Header>>, StoreError> {
+        Ok(self.inner.read().uncles.get(hash).cloned())
+    }
+
+    fn get_block_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError> {
+        Ok(self.inner.read().transactions.get(hash).cloned())
+    }
+
+    fn get_block_proposal_transactions(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<Vec<IndexedTransaction>>, StoreError> {
+        Ok(self.inner.read().proposal_transactions.get(hash).cloned())
+    }
+
+    fn get_block_ext(&self, hash: &H256) -> Result<Option<BlockExt>, StoreError> {
+        Ok(self.inner.read().exts.get(hash).cloned())
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Result<Option<H256>, StoreError> {
+        Ok(self.inner.read().index.get(&number).cloned())
+    }
+
+    fn get_transaction_address(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<TransactionAddress>, StoreError> {
+        Ok(self.inner.read().transaction_addresses.get(hash).cloned())
+    }
+
+    fn get_cell_spender(&self, out_point: &OutPoint) -> Result<Option<H256>, StoreError> {
+        Ok(self.inner.read().cell_spenders.get(out_point).cloned())
+    }
+
+    fn get_tip(&self) -> Result<Option<H256>, StoreError> {
+        Ok(self.inner.read().tip)
+    }
+
+    fn write(&self, batch: StoreBatch) -> Result<(), StoreError> {
+        let mut inner = self.inner.write();
+        for operation in batch.into_operations() {
+            match operation {
+                BatchOperation::InsertBlock(block) => {
+                    let hash = block.hash();
+                    inner.headers.insert(hash, block.header().clone());
+                    inner.uncles.insert(hash, block.uncles().to_vec());
+                    inner.transactions.insert(hash, block.commit_transactions().to_vec());
+                    inner
+                        .proposal_transactions
+                        .insert(hash, block.proposal_transactions().to_vec());
+                }
+                BatchOperation::InsertBlockExt(hash, ext) => {
+                    inner.exts.insert(hash, ext);
+                }
+                BatchOperation::AttachBlock {
+                    number,
+                    hash,
+                    transactions,
+                    spent,
+                } => {
+                    inner.index.insert(number, hash);
+                    inner.cell_spenders.extend(spent);
+                    for (index, tx_hash) in transactions.into_iter().enumerate() {
+                        let address = TransactionAddress {
+                            block_hash: hash,
+                            index: index as u32,
+                        };
+                        inner.transaction_addresses.insert(tx_hash, address);
+                    }
+                }
+                BatchOperation::DetachBlock {
+                    number,
+                    transactions,
+                    spent,
+                } => {
+                    inner.index.remove(&number);
+                    for out_point in &spent {
+                        inner.cell_spenders.remove(out_point);
+                    }
+                    for tx_hash in &transactions {
+                        inner.transaction_addresses.remove(tx_hash);
+                    }
+                }
+                BatchOperation::InsertTip(hash) => {
+                    inner.tip = Some(hash);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outputs spent by the committed transactions of the block, cellbase excluded.
+fn spent_out_points<'a>(
+    block: &'a IndexedBlock,
+) -> impl Iterator<Item = (OutPoint, &'a IndexedTransaction)> + 'a {
+    block
+        .commit_transactions()
+        .iter()
+        .filter(|tx| !tx.is_cellbase())
+        .flat_map(|tx| tx.inputs.iter().map(move |input| (input.previous_output, tx)))
+}
+
+fn out_point_key(out_point: &OutPoint) -> Vec<u8> {
+    let mut key = out_point.hash.as_bytes().to_vec();
+    key.extend_from_slice(&out_point.index.to_be_bytes());
+    key
+}
+
+/// Big endian so the keys are sorted by number.
+fn number_key(number: BlockNumber) -> [u8; 8] {
+    let mut key = [0u8; 8];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = (number >> (8 * (7 - i))) as u8;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+    use util::{CellInput, Transaction};
+
+    fn new_block(number: BlockNumber, mut transactions: Vec<IndexedTransaction>) -> IndexedBlock {
+        let cellbase = Transaction::new_cellbase(number, vec![]);
+        let header = Header {
+            number,
+            ..Header::default()
+        };
+        transactions.insert(0, cellbase.into());
+        IndexedBlock::new(header, vec![], transactions, vec![])
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::default();
+        let block = new_block(1, vec![]);
+        let hash = block.hash();
+        let tx_hash = block.commit_transactions()[0].hash();
+
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&block);
+        batch.attach_block(&block);
+        batch.insert_tip(hash);
+        store.write(batch).expect("write should be ok");
+
+        assert_eq!(store.get_block(&hash), Ok(Some(block.clone())));
+        assert_eq!(store.get_block_hash(1), Ok(Some(hash)));
+        assert_eq!(
+            store.get_transaction(&tx_hash),
+            Ok(Some(block.commit_transactions()[0].clone()))
+        );
+        assert_eq!(store.get_tip(), Ok(Some(hash)));
+
+        let out_point = OutPoint {
+            hash: tx_hash,
+            index: 0,
+        };
+        let spend: IndexedTransaction = Transaction {
+            inputs: vec![CellInput::new(out_point, vec![])],
+            ..Transaction::default()
+        }
+        .into();
+        let child = new_block(2, vec![spend.clone()]);
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&child);
+        batch.attach_block(&child);
+        store.write(batch).expect("write should be ok");
+        assert_eq!(store.get_cell_spender(&out_point), Ok(Some(spend.hash())));
+
+        let snapshot = store.snapshot();
+        let mut batch = StoreBatch::default();
+        batch.detach_block(&child);
+        batch.detach_block(&block);
+        store.write(batch).expect("write should be ok");
+        assert_eq!(store.get_block_hash(1), Ok(None));
+        assert_eq!(store.get_transaction(&tx_hash), Ok(None));
+        assert_eq!(store.get_cell_spender(&out_point), Ok(None));
+        assert_eq!(store.get_block(&hash), Ok(Some(block)));
+
+        let restored = MemoryStore::from_snapshot(snapshot.clone());
+        assert_eq!(restored.get_block_hash(1), Ok(Some(hash)));
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_rocksdb_store() {
+        let dir = tempfile::tempdir().expect("create temp dir should be ok");
+        let block = new_block(1, vec![]);
+        let hash = block.hash();
+        let tx_hash = block.commit_transactions()[0].hash();
+        let ext = BlockExt {
+            total_difficulty: 7,
+        };
+        {
+            let store = RocksDBStore::open(dir.path()).expect("open should be ok");
+            assert_eq!(store.get_tip(), Ok(None));
+            let mut batch = StoreBatch::default();
+            batch.insert_block(&block);
+            batch.insert_block_ext(hash, ext);
+            batch.attach_block(&block);
+            batch.insert_tip(hash);
+            store.write(batch).expect("write should be ok");
+        }
+
+        // Reopening keeps the chain
+        let store = RocksDBStore::open(dir.path()).expect("reopen should be ok");
+        assert_eq!(store.get_block(&hash), Ok(Some(block.clone())));
+        assert_eq!(store.get_block_ext(&hash), Ok(Some(ext)));
+        assert_eq!(store.get_block_hash(1), Ok(Some(hash)));
+        assert_eq!(
+            store.get_transaction(&tx_hash),
+            Ok(Some(block.commit_transactions()[0].clone()))
+        );
+        assert_eq!(store.get_tip(), Ok(Some(hash)));
+
+        // An undecodable value is reported instead of panicking
+        let meta = store.cf(COLUMN_META);
+        store.db.put_cf(meta, META_TIP_KEY, &[0xff]).expect("put should be ok");
+        assert_eq!(store.get_tip(), Err(StoreError::Codec(CodecError::Version(0xff))));
+
+        // A database of another schema version is refused
+        let version = (SCHEMA_VERSION + 1).to_le_bytes();
+        store.db.put_cf(meta, META_SCHEMA_VERSION_KEY, &version).expect("put should be ok");
+        drop(store);
+        match RocksDBStore::open(dir.path()) {
+            Err(StoreError::SchemaVersion { expected, actual }) => {
+                assert_eq!(expected, SCHEMA_VERSION);
+                assert_eq!(actual, SCHEMA_VERSION + 1);
+            }
+            _ => panic!("schema version mismatch expected"),
+        }
+    }
+}
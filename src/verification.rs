@@ -0,0 +1,789 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+
+use channel::{self, Sender};
+use lru_cache::LruCache;
+use parking_lot::Mutex;
+
+use util::{
+    Shared,
+    Consensus,
+    ChainStore,
+    PowEngine,
+    Capacity,
+    Header,
+    IndexedBlock,
+    IndexedTransaction,
+    H256,
+    StoreError,
+    now_ms,
+};
+
+/// How far the block timestamp may be ahead of the local clock, in milliseconds.
+pub const ALLOWED_FUTURE_BLOCKTIME: u64 = 15 * 1000;
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 1024;
+pub const DEFAULT_TRANSACTION_CACHE_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The header is invalid
+    Header(HeaderError),
+    /// The proof of work does not match the header
+    Pow,
+    /// The body does not match the commitments of the header
+    Body(BodyError),
+    /// The uncles are invalid
+    Uncles(UnclesError),
+    /// The commit transaction at the index is invalid
+    Transaction(usize, TransactionError),
+    /// The cellbase transaction is invalid
+    Cellbase(CellbaseError),
+    /// The chain needed to verify the block cannot be read
+    Store(StoreError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HeaderError {
+    /// The parent header is not in the store
+    UnknownParent,
+    /// The number is not the parent number plus one
+    Number { parent: u64, actual: u64 },
+    /// The timestamp is not later than the parent timestamp
+    TimestampTooOld,
+    /// The timestamp is too far in the future
+    TimestampTooNew,
+    /// The difficulty is not the one calculated from the parent
+    Difficulty { expected: u64, actual: u64 },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BodyError {
+    /// `Header::transactions_root` is not the root of the commit transactions
+    TransactionsRoot { expected: H256, actual: H256 },
+    /// `Header::proposals_root` is not the root of the proposal transactions
+    ProposalsRoot { expected: H256, actual: H256 },
+    /// `Header::uncles_hash` is not the hash of the uncles
+    UnclesHash { expected: H256, actual: H256 },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum UnclesError {
+    /// More uncles than `Consensus::max_uncles_len`
+    TooMany { max: usize, actual: usize },
+    /// The number of uncles does not match `Header::uncles_count`
+    Count { expected: u32, actual: usize },
+    /// The uncle is not older than the block or older than `Consensus::max_uncles_age`
+    InvalidNumber { index: usize },
+    /// The same uncle is included twice
+    Duplicate { index: usize },
+    /// The proof of work of the uncle is invalid
+    Pow { index: usize },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TransactionError {
+    /// Transaction has no inputs
+    MissingInputs,
+    /// Transaction has no outputs
+    MissingOutputs,
+    /// The same outpoint is spent twice
+    DuplicateInputs,
+    /// Only the first transaction in the block can be cellbase
+    UnexpectedCellbase,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CellbaseError {
+    /// The first transaction is not cellbase
+    Missing,
+    /// Cellbase outputs exceed the block reward
+    InvalidReward { max: Capacity, actual: Capacity },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CacheStats {
+    pub block_hits: u64,
+    pub block_misses: u64,
+    pub transaction_hits: u64,
+    pub transaction_misses: u64,
+}
+
+/// Hashes of the blocks and transactions which have passed verification.
+///
+/// Only successes are cached because some failures, such as an unknown parent, depend on the
+/// chain state when the block is verified.
+#[derive(Debug)]
+pub struct VerificationCache {
+    blocks: LruCache<H256, ()>,
+    transactions: LruCache<H256, ()>,
+    stats: CacheStats,
+}
+
+impl Default for VerificationCache {
+    fn default() -> Self {
+        VerificationCache::new(DEFAULT_BLOCK_CACHE_SIZE, DEFAULT_TRANSACTION_CACHE_SIZE)
+    }
+}
+
+impl VerificationCache {
+    pub fn new(block_capacity: usize, transaction_capacity: usize) -> Self {
+        VerificationCache {
+            blocks: LruCache::new(block_capacity),
+            transactions: LruCache::new(transaction_capacity),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn contains_block(&mut self, hash: &H256) -> bool {
+        let hit = self.blocks.contains_key(hash);
+        if hit {
+            self.stats.block_hits += 1;
+        } else {
+            self.stats.block_misses += 1;
+        }
+        hit
+    }
+
+    pub fn insert_block(&mut self, hash: H256) {
+        self.blocks.insert(hash, ());
+    }
+
+    pub fn contains_transaction(&mut self, hash: &H256) -> bool {
+        let hit = self.transactions.contains_key(hash);
+        if hit {
+            self.stats.transaction_hits += 1;
+        } else {
+            self.stats.transaction_misses += 1;
+        }
+        hit
+    }
+
+    pub fn insert_transaction(&mut self, hash: H256) {
+        self.transactions.insert(hash, ());
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+pub trait Verifier {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error>;
+}
+
+/// Runs the verifiers in sequence and returns the first failure.
+pub struct BlockVerifier {
+    verifiers: Vec<Box<dyn Verifier + Send>>,
+}
+
+impl BlockVerifier {
+    /// Creates the full pipeline: header, pow, body, uncles, transactions and cellbase.
+    ///
+    /// Transactions are verified on `transaction_workers` threads, or on the calling thread if
+    /// it is 0.
+    pub fn new<CS, P>(shared: &Shared<CS>, pow: &P, transaction_workers: usize) -> Self
+    where
+        CS: ChainStore + Send + Sync + 'static,
+        P: PowEngine + Clone + Send + 'static,
+    {
+        BlockVerifier::with_verifiers(vec![
+            Box::new(HeaderVerifier::new(shared.clone())),
+            Box::new(PowVerifier::new(pow.clone())),
+            Box::new(BodyVerifier),
+            Box::new(UnclesVerifier::new(shared.consensus.clone(), pow.clone())),
+            Box::new(TransactionsVerifier::new(
+                transaction_workers,
+                Arc::clone(&shared.verification_cache),
+            )),
+            Box::new(CellbaseVerifier::new(shared.consensus.initial_block_reward)),
+        ])
+    }
+
+    pub fn with_verifiers(verifiers: Vec<Box<dyn Verifier + Send>>) -> Self {
+        BlockVerifier { verifiers }
+    }
+}
+
+impl Verifier for BlockVerifier {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        self.verifiers.iter().try_for_each(|verifier| verifier.verify(block))
+    }
+}
+
+pub struct HeaderVerifier<CS> {
+    shared: Shared<CS>,
+}
+
+impl<CS: ChainStore> HeaderVerifier<CS> {
+    pub fn new(shared: Shared<CS>) -> Self {
+        HeaderVerifier { shared }
+    }
+
+    fn verify_header(&self, header: &Header) -> Result<(), Error> {
+        let parent = self
+            .shared
+            .store
+            .get_header(&header.parent_hash)
+            .map_err(Error::Store)?
+            .ok_or(Error::Header(HeaderError::UnknownParent))?;
+        if header.number != parent.number + 1 {
+            return Err(Error::Header(HeaderError::Number {
+                parent: parent.number,
+                actual: header.number,
+            }));
+        }
+        if header.timestamp <= parent.timestamp {
+            return Err(Error::Header(HeaderError::TimestampTooOld));
+        }
+        if header.timestamp > now_ms() + ALLOWED_FUTURE_BLOCKTIME {
+            return Err(Error::Header(HeaderError::TimestampTooNew));
+        }
+        let expected = self.shared.calculate_difficulty(&parent).map_err(Error::Store)?;
+        if header.difficulty != expected {
+            return Err(Error::Header(HeaderError::Difficulty {
+                expected,
+                actual: header.difficulty,
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl<CS: ChainStore> Verifier for HeaderVerifier<CS> {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        self.verify_header(block.header())
+    }
+}
+
+pub struct PowVerifier<P> {
+    pow: P,
+}
+
+impl<P: PowEngine> PowVerifier<P> {
+    pub fn new(pow: P) -> Self {
+        PowVerifier { pow }
+    }
+}
+
+impl<P: PowEngine> Verifier for PowVerifier<P> {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        if self.pow.verify_header(block.header()) {
+            Ok(())
+        } else {
+            Err(Error::Pow)
+        }
+    }
+}
+
+/// Checks the body against the commitments of the header, which the block hash covers.
+pub struct BodyVerifier;
+
+impl BodyVerifier {
+    fn verify_body(&self, block: &IndexedBlock) -> Result<(), BodyError> {
+        let header = block.header();
+        if header.transactions_root != block.transactions_root() {
+            return Err(BodyError::TransactionsRoot {
+                expected: header.transactions_root,
+                actual: block.transactions_root(),
+            });
+        }
+        if header.proposals_root != block.proposals_root() {
+            return Err(BodyError::ProposalsRoot {
+                expected: header.proposals_root,
+                actual: block.proposals_root(),
+            });
+        }
+        if header.uncles_hash != block.uncles_hash() {
+            return Err(BodyError::UnclesHash {
+                expected: header.uncles_hash,
+                actual: block.uncles_hash(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Verifier for BodyVerifier {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        self.verify_body(block).map_err(Error::Body)
+    }
+}
+
+pub struct UnclesVerifier<P> {
+    consensus: Consensus,
+    pow: P,
+}
+
+impl<P: PowEngine> UnclesVerifier<P> {
+    pub fn new(consensus: Consensus, pow: P) -> Self {
+        UnclesVerifier { consensus, pow }
+    }
+
+    fn verify_uncles(&self, block: &IndexedBlock) -> Result<(), UnclesError> {
+        if block.uncles().len() != block.header().uncles_count as usize {
+            return Err(UnclesError::Count {
+                expected: block.header().uncles_count,
+                actual: block.uncles().len(),
+            });
+        }
+        let max = self.consensus.max_uncles_len;
+        if block.uncles().len() > max {
+            return Err(UnclesError::TooMany {
+                max,
+                actual: block.uncles().len(),
+            });
+        }
+
+        let max_age = self.consensus.max_uncles_age as u64;
+        let mut included = HashSet::with_capacity(block.uncles().len());
+        for (index, uncle) in block.uncles().iter().enumerate() {
+            if uncle.number >= block.number() || uncle.number + max_age < block.number() {
+                return Err(UnclesError::InvalidNumber { index });
+            }
+            if !included.insert(uncle.hash()) {
+                return Err(UnclesError::Duplicate { index });
+            }
+            if !self.pow.verify_header(uncle) {
+                return Err(UnclesError::Pow { index });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<P: PowEngine> Verifier for UnclesVerifier<P> {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        self.verify_uncles(block).map_err(Error::Uncles)
+    }
+}
+
+/// Checks every commit transaction except cellbase on its own.
+///
+/// Transactions found in the verification cache are skipped. The rest are split into one chunk
+/// per worker, and the error of the lowest index is returned so the result does not depend on
+/// scheduling.
+pub struct TransactionsVerifier {
+    workers: usize,
+    job_sender: Option<Sender<TransactionsJob>>,
+    cache: Arc<Mutex<VerificationCache>>,
+}
+
+struct TransactionsJob {
+    block: Arc<IndexedBlock>,
+    indices: Vec<usize>,
+    chunk: usize,
+    responsor: Sender<(usize, Result<(), Error>)>,
+}
+
+impl TransactionsVerifier {
+    pub fn new(workers: usize, cache: Arc<Mutex<VerificationCache>>) -> Self {
+        if workers == 0 {
+            return TransactionsVerifier {
+                workers,
+                job_sender: None,
+                cache,
+            };
+        }
+
+        let (job_sender, job_receiver) = channel::unbounded::<TransactionsJob>();
+        for i in 0..workers {
+            let job_receiver = job_receiver.clone();
+            thread::Builder::new()
+                .name(format!("tx_verifier_{}", i))
+                .spawn(move || {
+                    // Exits when the verifier is dropped.
+                    while let Some(job) = job_receiver.recv() {
+                        let result = Self::verify_indices(&job.block, &job.indices);
+                        job.responsor.send((job.chunk, result));
+                    }
+                }).expect("Start transaction verifier failed");
+        }
+
+        TransactionsVerifier {
+            workers,
+            job_sender: Some(job_sender),
+            cache,
+        }
+    }
+
+    pub fn verify_transaction(tx: &IndexedTransaction) -> Result<(), TransactionError> {
+        if tx.is_cellbase() {
+            return Err(TransactionError::UnexpectedCellbase);
+        }
+        if tx.inputs.is_empty() {
+            return Err(TransactionError::MissingInputs);
+        }
+        if tx.outputs.is_empty() {
+            return Err(TransactionError::MissingOutputs);
+        }
+        let mut spent = HashSet::with_capacity(tx.inputs.len());
+        if !tx.inputs.iter().all(|input| spent.insert(input.previous_output)) {
+            return Err(TransactionError::DuplicateInputs);
+        }
+        Ok(())
+    }
+
+    fn verify_indices(block: &IndexedBlock, indices: &[usize]) -> Result<(), Error> {
+        indices.iter().try_for_each(|&index| {
+            Self::verify_transaction(&block.commit_transactions()[index])
+                .map_err(|err| Error::Transaction(index, err))
+        })
+    }
+
+    fn verify_in_workers(
+        &self,
+        job_sender: &Sender<TransactionsJob>,
+        block: &Arc<IndexedBlock>,
+        indices: &[usize],
+    ) -> Result<(), Error> {
+        let chunk_size = indices.len().div_ceil(self.workers);
+        let chunks = indices.len().div_ceil(chunk_size);
+        let (responsor, response) = channel::bounded(chunks);
+        for (chunk, chunk_indices) in indices.chunks(chunk_size).enumerate() {
+            job_sender.send(TransactionsJob {
+                block: Arc::clone(block),
+                indices: chunk_indices.to_vec(),
+                chunk,
+                responsor: responsor.clone(),
+            });
+        }
+
+        let mut results = vec![Ok(()); chunks];
+        for _ in 0..chunks {
+            let (chunk, result) = response.recv().expect("Transaction verifier stopped");
+            results[chunk] = result;
+        }
+        results.into_iter().collect()
+    }
+}
+
+impl Verifier for TransactionsVerifier {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        let indices: Vec<usize> = {
+            let mut cache = self.cache.lock();
+            // Skip cellbase
+            (1..block.commit_transactions().len())
+                .filter(|&index| {
+                    !cache.contains_transaction(&block.commit_transactions()[index].hash())
+                })
+                .collect()
+        };
+
+        match self.job_sender {
+            Some(ref job_sender) if !indices.is_empty() => {
+                self.verify_in_workers(job_sender, block, &indices)
+            }
+            _ => Self::verify_indices(block, &indices),
+        }?;
+
+        let mut cache = self.cache.lock();
+        for index in indices {
+            cache.insert_transaction(block.commit_transactions()[index].hash());
+        }
+        Ok(())
+    }
+}
+
+pub struct CellbaseVerifier {
+    block_reward: Capacity,
+}
+
+impl CellbaseVerifier {
+    pub fn new(block_reward: Capacity) -> Self {
+        CellbaseVerifier { block_reward }
+    }
+}
+
+impl Verifier for CellbaseVerifier {
+    fn verify(&self, block: &Arc<IndexedBlock>) -> Result<(), Error> {
+        let cellbase = match block.commit_transactions().first() {
+            Some(tx) if tx.is_cellbase() => tx,
+            _ => return Err(Error::Cellbase(CellbaseError::Missing)),
+        };
+        let actual = cellbase.outputs_capacity();
+        if actual > self.block_reward {
+            return Err(Error::Cellbase(CellbaseError::InvalidReward {
+                max: self.block_reward,
+                actual,
+            }));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::{CellInput, CellOutput, MemoryStore, OutPoint, StoreBatch, Transaction, H256};
+    use util::{transactions_root, uncles_hash, DummyPowEngine, RejectPowEngine};
+
+    fn block(transactions: Vec<IndexedTransaction>) -> Arc<IndexedBlock> {
+        Arc::new(IndexedBlock::new(Header::default(), vec![], transactions, vec![]))
+    }
+
+    /// A header verifier with the parent of the headers to verify in its store.
+    fn header_verifier() -> (HeaderVerifier<MemoryStore>, IndexedBlock) {
+        let parent = IndexedBlock::default();
+        let mut batch = StoreBatch::default();
+        batch.insert_block(&parent);
+        let store = MemoryStore::default();
+        store.write(batch).expect("write should be ok");
+        let verifier = HeaderVerifier::new(
+            Shared::new(Consensus::default(), store).expect("init should be ok"),
+        );
+        (verifier, parent)
+    }
+
+    fn header_block(header: Header, uncles: Vec<Header>) -> Arc<IndexedBlock> {
+        Arc::new(IndexedBlock::new(header, uncles, vec![], vec![]))
+    }
+
+    #[test]
+    fn test_header_number() {
+        let (verifier, parent) = header_verifier();
+        let mut header = Header {
+            parent_hash: parent.hash(),
+            number: 2,
+            timestamp: 1,
+            ..Header::default()
+        };
+        assert_eq!(
+            verifier.verify(&Arc::new(IndexedBlock::new(header.clone(), vec![], vec![], vec![]))),
+            Err(Error::Header(HeaderError::Number { parent: 0, actual: 2 }))
+        );
+        header.number = 1;
+        assert_eq!(
+            verifier.verify(&Arc::new(IndexedBlock::new(header, vec![], vec![], vec![]))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_header_timestamp_and_difficulty() {
+        let (verifier, parent) = header_verifier();
+        let expected = verifier
+            .shared
+            .calculate_difficulty(parent.header())
+            .expect("calculate difficulty should be ok");
+        let header = Header {
+            parent_hash: parent.hash(),
+            number: 1,
+            timestamp: parent.header().timestamp + 1,
+            difficulty: expected,
+            ..Header::default()
+        };
+        assert_eq!(verifier.verify(&header_block(header.clone(), vec![])), Ok(()));
+
+        let old = Header {
+            timestamp: parent.header().timestamp,
+            ..header.clone()
+        };
+        assert_eq!(
+            verifier.verify(&header_block(old, vec![])),
+            Err(Error::Header(HeaderError::TimestampTooOld))
+        );
+        let new = Header {
+            timestamp: now_ms() + ALLOWED_FUTURE_BLOCKTIME + 60 * 1000,
+            ..header.clone()
+        };
+        assert_eq!(
+            verifier.verify(&header_block(new, vec![])),
+            Err(Error::Header(HeaderError::TimestampTooNew))
+        );
+        let difficulty = Header {
+            difficulty: expected + 1,
+            ..header
+        };
+        assert_eq!(
+            verifier.verify(&header_block(difficulty, vec![])),
+            Err(Error::Header(HeaderError::Difficulty {
+                expected,
+                actual: expected + 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_pow() {
+        let verifier = PowVerifier::new(RejectPowEngine::new(vec![1]));
+        let mut header = Header::default();
+        assert_eq!(verifier.verify(&header_block(header.clone(), vec![])), Ok(()));
+        header.nonce = 1;
+        assert_eq!(verifier.verify(&header_block(header, vec![])), Err(Error::Pow));
+    }
+
+    #[test]
+    fn test_body() {
+        let cellbase = Transaction::new_cellbase(100, vec![]);
+        let transactions = vec![cellbase.clone().into(), transaction(0).into()];
+        let proposals = vec![transaction(1).into()];
+        let uncles = vec![Header {
+            number: 1,
+            ..Header::default()
+        }];
+        let header = Header {
+            number: 2,
+            transactions_root: transactions_root(&transactions),
+            proposals_root: transactions_root(&proposals),
+            uncles_hash: uncles_hash(&uncles),
+            uncles_count: 1,
+            ..Header::default()
+        };
+        let block = |transactions, proposals, uncles| {
+            Arc::new(IndexedBlock::new(header.clone(), uncles, transactions, proposals))
+        };
+        assert_eq!(
+            BodyVerifier.verify(&block(transactions.clone(), proposals.clone(), uncles.clone())),
+            Ok(())
+        );
+
+        // A tampered body under the same header keeps the block hash
+        let tampered = vec![cellbase.into(), transaction(2).into()];
+        let tampered_block = block(tampered.clone(), proposals.clone(), uncles.clone());
+        assert_eq!(tampered_block.hash(), header.hash());
+        assert_eq!(
+            BodyVerifier.verify(&tampered_block),
+            Err(Error::Body(BodyError::TransactionsRoot {
+                expected: header.transactions_root,
+                actual: transactions_root(&tampered),
+            }))
+        );
+        assert_eq!(
+            BodyVerifier.verify(&block(transactions.clone(), vec![], uncles.clone())),
+            Err(Error::Body(BodyError::ProposalsRoot {
+                expected: header.proposals_root,
+                actual: H256::default(),
+            }))
+        );
+        assert_eq!(
+            BodyVerifier.verify(&block(transactions, proposals, vec![])),
+            Err(Error::Body(BodyError::UnclesHash {
+                expected: header.uncles_hash,
+                actual: H256::default(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_uncles() {
+        let consensus = Consensus {
+            max_uncles_age: 2,
+            max_uncles_len: 2,
+            ..Consensus::default()
+        };
+        let verifier = UnclesVerifier::new(consensus, RejectPowEngine::new(vec![7]));
+        let block = |uncles: Vec<Header>| {
+            let header = Header {
+                number: 5,
+                uncles_count: uncles.len() as u32,
+                ..Header::default()
+            };
+            header_block(header, uncles)
+        };
+        let uncle = |number, nonce| Header {
+            number,
+            nonce,
+            ..Header::default()
+        };
+
+        assert_eq!(verifier.verify(&block(vec![uncle(4, 0), uncle(3, 1)])), Ok(()));
+        let header = Header {
+            number: 5,
+            uncles_count: 2,
+            ..Header::default()
+        };
+        assert_eq!(
+            verifier.verify(&header_block(header, vec![uncle(4, 0)])),
+            Err(Error::Uncles(UnclesError::Count { expected: 2, actual: 1 }))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![uncle(4, 0), uncle(4, 1), uncle(4, 2)])),
+            Err(Error::Uncles(UnclesError::TooMany { max: 2, actual: 3 }))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![uncle(4, 0), uncle(5, 1)])),
+            Err(Error::Uncles(UnclesError::InvalidNumber { index: 1 }))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![uncle(2, 0)])),
+            Err(Error::Uncles(UnclesError::InvalidNumber { index: 0 }))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![uncle(4, 0), uncle(4, 0)])),
+            Err(Error::Uncles(UnclesError::Duplicate { index: 1 }))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![uncle(4, 7)])),
+            Err(Error::Uncles(UnclesError::Pow { index: 0 }))
+        );
+        let verifier = UnclesVerifier::new(Consensus::default(), DummyPowEngine::default());
+        assert_eq!(verifier.verify(&block(vec![])), Ok(()));
+    }
+
+    #[test]
+    fn test_cellbase_reward() {
+        let verifier = CellbaseVerifier::new(100);
+        assert_eq!(
+            verifier.verify(&block(vec![])),
+            Err(Error::Cellbase(CellbaseError::Missing))
+        );
+        assert_eq!(
+            verifier.verify(&block(vec![Transaction::new_cellbase(101, vec![]).into()])),
+            Err(Error::Cellbase(CellbaseError::InvalidReward { max: 100, actual: 101 }))
+        );
+        let cellbase = Transaction::new_cellbase(100, vec![]).into();
+        assert_eq!(verifier.verify(&block(vec![cellbase])), Ok(()));
+    }
+
+    fn transaction(index: u32) -> Transaction {
+        Transaction {
+            inputs: vec![CellInput::new(
+                OutPoint {
+                    hash: H256::default(),
+                    index,
+                },
+                vec![],
+            )],
+            outputs: vec![CellOutput::default()],
+            ..Transaction::default()
+        }
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut transactions = vec![Transaction::new_cellbase(100, vec![])];
+        transactions.extend((0..10).map(transaction));
+        transactions[4].outputs.clear();
+        transactions[7] = Transaction::new_cellbase(100, vec![]);
+        let block = block(transactions.into_iter().map(Into::into).collect());
+
+        for workers in 0..4 {
+            let cache = Arc::new(Mutex::new(VerificationCache::default()));
+            let verifier = TransactionsVerifier::new(workers, cache);
+            assert_eq!(
+                verifier.verify(&block),
+                Err(Error::Transaction(4, TransactionError::MissingOutputs))
+            );
+        }
+    }
+
+    #[test]
+    fn test_verification_cache() {
+        let mut cache = VerificationCache::new(1, 1);
+        let hash = IndexedBlock::default().hash();
+        assert!(!cache.contains_block(&hash));
+        cache.insert_block(hash);
+        assert!(cache.contains_block(&hash));
+        assert!(!cache.contains_transaction(&hash));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                block_hits: 1,
+                block_misses: 1,
+                transaction_hits: 0,
+                transaction_misses: 1,
+            }
+        );
+    }
+}